@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+use image::imageops::FilterType;
+use log::error;
+use reqwest::blocking::Client;
+
+// Spotify's largest covers are 640x640 JPEGs well under this
+const MAX_IMAGE_BYTES: u64 = 4 * 1024 * 1024;
+// Enough pixels for a stable colour, few enough to count them quickly
+const SAMPLE_SIZE: u32 = 32;
+// Pixels whose channels differ by less than this count as grey
+const MIN_SATURATION: u8 = 24;
+
+/// Dominant colours of album covers, cached by image URL. Every track of an
+/// album shares its cover URL, so each album is only downloaded once.
+#[derive(Clone)]
+pub struct AlbumColours {
+    http_client: Client,
+    cache: Arc<Mutex<HashMap<String, u32>>>,
+}
+
+impl AlbumColours {
+    pub fn new(http_client: Client) -> AlbumColours {
+        AlbumColours {
+            http_client,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The dominant colour of the cover at `image_url` as `0xRRGGBB`, or
+    /// `None` if it could not be downloaded or decoded.
+    pub fn get(&self, image_url: &str) -> Option<u32> {
+        if let Some(&colour) = self.cache.lock().unwrap().get(image_url) {
+            return Some(colour);
+        }
+        let colour = match self.fetch(image_url) {
+            Ok(colour) => colour,
+            Err(why) => {
+                error!(
+                    "Could not get album colour of {}: {:?}",
+                    image_url, why
+                );
+                return None;
+            }
+        };
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(image_url.to_string(), colour);
+        Some(colour)
+    }
+
+    fn fetch(
+        &self,
+        image_url: &str,
+    ) -> Result<u32, Box<dyn std::error::Error>> {
+        let response =
+            self.http_client.get(image_url).send()?.error_for_status()?;
+        let mut bytes = Vec::new();
+        response.take(MAX_IMAGE_BYTES + 1).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 > MAX_IMAGE_BYTES {
+            return Err("Album cover is too large".into());
+        }
+        let image = image::load_from_memory(&bytes)?;
+        let pixels = image
+            .resize_exact(SAMPLE_SIZE, SAMPLE_SIZE, FilterType::Triangle)
+            .to_rgb8();
+        let pixels: Vec<[u8; 3]> =
+            pixels.pixels().map(|pixel| pixel.0).collect();
+        dominant_colour(&pixels).ok_or_else(|| "Album cover is empty".into())
+    }
+}
+
+/// Quantizes pixels into 4096 buckets, 16 levels per channel, and averages
+/// the fullest one. Greys are only considered when a cover has nothing else,
+/// so a small coloured subject wins over a black or white background.
+fn dominant_colour(pixels: &[[u8; 3]]) -> Option<u32> {
+    let saturated: Vec<[u8; 3]> = pixels
+        .iter()
+        .copied()
+        .filter(|pixel| {
+            let max = pixel.iter().max().unwrap_or(&0);
+            let min = pixel.iter().min().unwrap_or(&0);
+            max - min >= MIN_SATURATION
+        })
+        .collect();
+    let pixels = if saturated.is_empty() {
+        pixels
+    } else {
+        &saturated
+    };
+
+    let mut buckets: HashMap<[u8; 3], (u32, [u32; 3])> = HashMap::new();
+    for pixel in pixels {
+        let (count, sums) = buckets.entry(pixel.map(|c| c >> 4)).or_default();
+        *count += 1;
+        for (sum, &channel) in sums.iter_mut().zip(pixel) {
+            *sum += u32::from(channel);
+        }
+    }
+    let (count, [r, g, b]) =
+        buckets.into_values().max_by_key(|&(count, _)| count)?;
+    Some((r / count) << 16 | (g / count) << 8 | (b / count))
+}
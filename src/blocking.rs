@@ -0,0 +1,15 @@
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::task;
+
+/// Runs blocking work, such as a Spotify request with its retries, from
+/// anywhere. On a runtime worker the worker's other tasks are handed to
+/// another thread first, so a slow request doesn't stall every handler.
+/// Elsewhere, such as on a thread of its own, `f` simply runs.
+pub fn run<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
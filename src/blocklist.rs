@@ -0,0 +1,64 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serenity::model::id::UserId;
+
+use crate::playlist_manager::normalize;
+use crate::spotify_client::TrackInfo;
+
+/// Artists and members a server has kept out of the playlist.
+#[derive(Clone, Default)]
+pub struct Blocklist {
+    /// Artist names as given, keyed by their normalized form so "AC/DC" and
+    /// "acdc" are the same entry.
+    artists: BTreeMap<String, String>,
+    users: BTreeSet<UserId>,
+}
+
+impl Blocklist {
+    /// Returns whether the artist was not already blocked.
+    pub fn block_artist(&mut self, name: &str) -> bool {
+        self.artists
+            .insert(normalize(name), name.to_string())
+            .is_none()
+    }
+
+    /// Returns whether the artist was blocked.
+    pub fn unblock_artist(&mut self, name: &str) -> bool {
+        self.artists.remove(&normalize(name)).is_some()
+    }
+
+    /// Returns whether the member was not already blocked.
+    pub fn block_user(&mut self, user_id: UserId) -> bool {
+        self.users.insert(user_id)
+    }
+
+    /// Returns whether the member was blocked.
+    pub fn unblock_user(&mut self, user_id: UserId) -> bool {
+        self.users.remove(&user_id)
+    }
+
+    pub fn is_user_blocked(&self, user_id: UserId) -> bool {
+        self.users.contains(&user_id)
+    }
+
+    /// The first of the track's artists that is blocked, if any.
+    pub fn blocked_artist<'a>(&self, track: &'a TrackInfo) -> Option<&'a str> {
+        track
+            .artists
+            .iter()
+            .find(|artist| self.artists.contains_key(&normalize(artist)))
+            .map(String::as_str)
+    }
+
+    pub fn artists(&self) -> impl Iterator<Item = &str> {
+        self.artists.values().map(String::as_str)
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = UserId> + '_ {
+        self.users.iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.artists.is_empty() && self.users.is_empty()
+    }
+}
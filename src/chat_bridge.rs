@@ -0,0 +1,301 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use reqwest::blocking::Client;
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
+use tokio::runtime::Handle;
+
+use crate::blocklist::Blocklist;
+use crate::config::{self, env_or};
+use crate::discord_client::{self, find_track_ids};
+use crate::http_client;
+use crate::matrix::MatrixChat;
+use crate::moderation::{self, Moderation};
+use crate::playlist_manager::{AddOutcome, PlaylistCache};
+use crate::quota::{self, SubmissionQuota};
+use crate::spotify_client::{self, SpotifyClient};
+use crate::systemd;
+use crate::telegram::TelegramChat;
+use crate::twitch::TwitchChat;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// A message posted in a chat other than Discord.
+pub struct ChatMessage {
+    pub author: String,
+    pub text: String,
+    /// The chat and message, in the source's own terms, to reply to.
+    pub chat_id: String,
+    pub message_id: String,
+}
+
+/// A chat other than Discord that members link tracks in. Discord's client
+/// is event driven and stays separate, but shares the same pipeline through
+/// `PlaylistCache::add_unless_present`.
+pub trait ChatSource: Send {
+    /// Named in logs and credits, such as "Twitch". Also names the source's
+    /// `<NAME>_ANNOUNCE_CHANNEL_ID` setting.
+    fn name(&self) -> &'static str;
+
+    /// Waits for the next messages, connecting first if need be. After an
+    /// error, the next call reconnects.
+    fn receive(
+        &mut self,
+    ) -> Result<Vec<ChatMessage>, Box<dyn std::error::Error>>;
+
+    /// Answers a message in its chat. Sources that can only read ignore this.
+    fn reply(
+        &mut self,
+        _message: &ChatMessage,
+        _content: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// Every chat other than Discord that is configured.
+pub fn sources_from_env(http_client: &Client) -> Vec<Box<dyn ChatSource>> {
+    let mut sources: Vec<Box<dyn ChatSource>> = Vec::new();
+    if let Some(twitch) = TwitchChat::from_env() {
+        sources.push(Box::new(twitch));
+    }
+    if let Some(telegram) = TelegramChat::from_env(http_client.clone()) {
+        sources.push(Box::new(telegram));
+    }
+    if let Some(matrix) = MatrixChat::from_env(http_client.clone()) {
+        sources.push(Box::new(matrix));
+    }
+    sources
+}
+
+/// Runs the configured chats without connecting to Discord, for
+/// communities that don't use it. Returns on SIGINT or SIGTERM.
+pub async fn run_without_discord() {
+    let http_client = http_client::build();
+    let sources = sources_from_env(&http_client);
+    if sources.is_empty() {
+        error!(
+            "DISCORD_ENABLED is false but no other chat is configured, set \
+             MATRIX_HOMESERVER_URL, TELEGRAM_BOT_TOKEN or TWITCH_CHANNEL"
+        );
+        return;
+    }
+    let market = config::spotify_market().unwrap_or_default();
+    let mut spotify_client = SpotifyClient::new(http_client, market);
+    let playlist_cache =
+        Arc::new(PlaylistCache::new(spotify_client::PLAYLIST_ID));
+    if let Err(why) = playlist_cache.refresh(&mut spotify_client) {
+        error!("Could not load the collaborative playlist: {:?}", why);
+    }
+    for source in sources {
+        ChatBridge::start(
+            source,
+            None,
+            spotify_client.clone(),
+            playlist_cache.clone(),
+        );
+    }
+    systemd::notify_ready();
+    discord_client::wait_for_shutdown_signal().await;
+    info!("Shutting down");
+    systemd::notify_stopping();
+}
+
+/// Adds the Spotify tracks linked in a `ChatSource` to the collaborative
+/// playlist, replying in the chat where the source can. When running
+/// alongside Discord, added tracks are credited to their chatter in
+/// `<NAME>_ANNOUNCE_CHANNEL_ID` if set. Other chats have no Discord server,
+/// so no blocklist applies, but chatters get the same link cap, daily quota
+/// and spam checks as Discord members.
+pub struct ChatBridge {
+    source: Box<dyn ChatSource>,
+    discord: Option<Arc<Http>>,
+    announce_channel: Option<ChannelId>,
+    spotify_client: SpotifyClient,
+    playlist_cache: Arc<PlaylistCache>,
+    /// Keyed by `<source>:<author>`, as chatters aren't Discord users.
+    submission_quota: SubmissionQuota<String>,
+    moderation: Moderation<String>,
+    runtime: Handle,
+}
+
+impl ChatBridge {
+    /// Reads the chat on a thread of its own. Must be called from within the
+    /// bot's runtime.
+    pub fn start(
+        source: Box<dyn ChatSource>,
+        discord: Option<Arc<Http>>,
+        spotify_client: SpotifyClient,
+        playlist_cache: Arc<PlaylistCache>,
+    ) {
+        let setting =
+            format!("{}_ANNOUNCE_CHANNEL_ID", source.name().to_uppercase());
+        let announce_channel = match env_or(&setting, 0u64) {
+            0 => None,
+            channel_id => Some(ChannelId(channel_id)),
+        };
+        let mut bridge = ChatBridge {
+            source,
+            discord,
+            announce_channel,
+            spotify_client,
+            playlist_cache,
+            submission_quota: SubmissionQuota::default(),
+            moderation: Moderation::default(),
+            runtime: Handle::current(),
+        };
+        thread::spawn(move || loop {
+            match bridge.source.receive() {
+                Ok(messages) => {
+                    for message in messages {
+                        bridge.add_linked_tracks(&message);
+                    }
+                }
+                Err(why) => {
+                    error!(
+                        "{} chat disconnected: {:?}",
+                        bridge.source.name(),
+                        why
+                    );
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        });
+    }
+
+    fn add_linked_tracks(&mut self, message: &ChatMessage) {
+        let name = self.source.name();
+        let track_ids = find_track_ids(&message.text);
+        if track_ids.is_empty() {
+            return;
+        }
+        let chatter = format!("{}:{}", name, message.author);
+        if self.moderation.is_muted(&chatter) {
+            info!("Ignoring links from muted chatter {}", chatter);
+            return;
+        }
+        let taken = match self.check_submission_limits(&chatter, &track_ids) {
+            Ok(taken) => taken,
+            Err(reason) => {
+                info!("Refusing links from {}: {}", chatter, reason);
+                self.reply(message, &reason);
+                return;
+            }
+        };
+        if let Some(flagged) = self.screen_for_spam(&chatter, &track_ids) {
+            warn!("Muting links from {}: {}", chatter, flagged.reason);
+            self.submission_quota.refund(&chatter, taken);
+            if let Some(http) = &self.discord {
+                let posted_by = format!("{} on {}", message.author, name);
+                self.runtime.block_on(discord_client::alert_moderators(
+                    http, posted_by, None, &flagged,
+                ));
+            }
+            return;
+        }
+        let mut added = 0;
+        let mut lines = Vec::new();
+        for track_id in track_ids {
+            match self.playlist_cache.add_unless_present(
+                &mut self.spotify_client,
+                &Blocklist::default(),
+                &track_id,
+            ) {
+                Ok(AddOutcome::Added(track)) => {
+                    info!(
+                        "{} added {} from {}",
+                        message.author, track.uri, name
+                    );
+                    let credit = format!(
+                        "**{}** — {}, linked by {} on {}",
+                        track.name,
+                        track.artists.join(", "),
+                        message.author,
+                        name
+                    );
+                    self.announce(credit);
+                    added += 1;
+                    lines.push(format!("Added {}", track.name));
+                }
+                Ok(AddOutcome::AlreadyInPlaylist(track)) => lines
+                    .push(format!("{} is already in the playlist", track.name)),
+                Ok(AddOutcome::Unplayable(track))
+                | Ok(AddOutcome::Blocked(track, _)) => {
+                    lines.push(format!("{} can't be added", track.name))
+                }
+                Err(why) => {
+                    warn!(
+                        "Could not add {} linked on {} by {}: {}",
+                        track_id, name, message.author, why
+                    );
+                    lines.push("Could not add that track".to_string());
+                }
+            }
+        }
+        // Only tracks that made it in count towards the quota
+        self.submission_quota
+            .refund(&chatter, taken.saturating_sub(added));
+        self.reply(message, &lines.join("\n"));
+    }
+
+    /// Enforces `MAX_LINKS_PER_MESSAGE` and the chatter's daily quota,
+    /// returning how many tracks were taken from the quota.
+    fn check_submission_limits(
+        &self,
+        chatter: &str,
+        track_ids: &[String],
+    ) -> Result<usize, String> {
+        let count = track_ids.iter().collect::<HashSet<_>>().len();
+        quota::check_link_count(count)?;
+        self.submission_quota
+            .take(chatter.to_string(), count)
+            .map(|()| count)
+            .map_err(|exceeded| exceeded.reason(count))
+    }
+
+    fn screen_for_spam(
+        &mut self,
+        chatter: &str,
+        track_ids: &[String],
+    ) -> Option<moderation::Flagged> {
+        // Tracks that can't be looked up are reported when they are added
+        let links = track_ids
+            .iter()
+            .map(|track_id| moderation::Link {
+                track_id: track_id.clone(),
+                artists: self
+                    .spotify_client
+                    .get_track(track_id)
+                    .map(|track| track.artists)
+                    .unwrap_or_default(),
+            })
+            .collect();
+        self.moderation.screen(chatter.to_string(), links)
+    }
+
+    fn reply(&mut self, message: &ChatMessage, content: &str) {
+        if let Err(why) = self.source.reply(message, content) {
+            error!("Could not reply on {}: {:?}", self.source.name(), why);
+        }
+    }
+
+    fn announce(&self, content: String) {
+        let (Some(http), Some(channel_id)) =
+            (&self.discord, self.announce_channel)
+        else {
+            return;
+        };
+        let result = self.runtime.block_on(channel_id.say(http, content));
+        if let Err(why) = result {
+            error!(
+                "Could not credit {} chatter: {:?}",
+                self.source.name(),
+                why
+            );
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::env;
+use std::fs;
+use std::str::FromStr;
+
+use log::{error, warn};
+
+use crate::feedback::FeedbackMode;
+use crate::secrets;
+
+/// Reads an optional setting from the environment, falling back to `default`
+/// when it is unset or cannot be parsed.
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// The deployment profile from `SONIC_ENV`, such as `dev` or `prod`.
+pub fn profile() -> Option<String> {
+    env::var("SONIC_ENV")
+        .ok()
+        .filter(|profile| !profile.is_empty())
+}
+
+/// Loads the settings in `.env.<profile>` for the profile in `SONIC_ENV`.
+/// Variables already in the environment win over the file, so one setting
+/// can be overridden without editing it. Runs before logging starts, so
+/// that the file can set `RUST_LOG`, and returns the profile loaded.
+pub fn load_profile() -> Result<Option<String>, String> {
+    let Some(profile) = profile() else {
+        return Ok(None);
+    };
+    let path = format!(".env.{profile}");
+    let contents = fs::read_to_string(&path)
+        .map_err(|why| format!("Could not read {path}: {why}"))?;
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = line
+            .strip_prefix("export ")
+            .unwrap_or(line)
+            .split_once('=')
+            .ok_or_else(|| {
+                format!("{path}:{}: expected NAME=value", number + 1)
+            })?;
+        let name = name.trim();
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        if env::var_os(name).is_none() {
+            env::set_var(name, value);
+        }
+    }
+    Ok(Some(profile))
+}
+
+/// Whether to skip writes to Spotify, from `DRY_RUN`. Defaults to on for
+/// every profile but `prod`, so a test deployment has to opt in to changing
+/// real playlists.
+pub fn dry_run() -> bool {
+    env_or(
+        "DRY_RUN",
+        profile().is_some_and(|profile| profile != "prod"),
+    )
+}
+
+/// The country whose catalogue tracks are looked up in, from
+/// `SPOTIFY_MARKET`: an ISO 3166-1 alpha-2 code such as `DE`, or `from_token`
+/// for the country of the logged in account. `validate` refuses anything
+/// else at startup, so later callers may treat an error as unset.
+pub fn spotify_market() -> Result<Option<String>, String> {
+    let Ok(market) = env::var("SPOTIFY_MARKET") else {
+        return Ok(None);
+    };
+    let is_country_code =
+        market.len() == 2 && market.chars().all(|c| c.is_ascii_uppercase());
+    if !is_country_code && market != "from_token" {
+        return Err(format!(
+            "{market:?} is not a country code such as DE, or from_token"
+        ));
+    }
+    Ok(Some(market))
+}
+
+/// Whether to connect to Discord, from `DISCORD_ENABLED`. Without it, only
+/// the other chats that are configured run.
+pub fn discord_enabled() -> bool {
+    env_or("DISCORD_ENABLED", true)
+}
+
+// Secrets the bot cannot start without, besides `DISCORD_TOKEN` when
+// connecting to Discord
+const REQUIRED_SECRETS: &[&str] =
+    &["SPOTIFY_CLIENT_ID", "SPOTIFY_CLIENT_SECRET"];
+// Settings read as whole numbers
+const NUMBER_SETTINGS: &[&str] = &[
+    "ANNOUNCEMENT_ROLE_ID",
+    "AUTO_FOLLOW_ARTIST_THRESHOLD",
+    "ERROR_FEEDBACK_DELETE_SECS",
+    "HEALTH_MAX_TRACK_MINUTES",
+    "HTTP_CONNECT_TIMEOUT_SECS",
+    "HTTP_POOL_IDLE_TIMEOUT_SECS",
+    "HTTP_POOL_MAX_IDLE_PER_HOST",
+    "HTTP_REQUEST_TIMEOUT_SECS",
+    "LOG_MAX_SIZE_MB",
+    "LOG_RETENTION",
+    "MATRIX_ANNOUNCE_CHANNEL_ID",
+    "MAX_LINKS_PER_MESSAGE",
+    "MAX_TRACKS_PER_USER_PER_DAY",
+    "MILESTONE_STEP",
+    "MODERATION_CHANNEL_ID",
+    "NEW_RELEASES_CHANNEL_ID",
+    "NEW_RELEASES_TOP_ARTISTS",
+    "QUARANTINE_CHANNEL_ID",
+    "QUIZ_DURATION_SECS",
+    "SONG_OF_THE_DAY_CHANNEL_ID",
+    "SONG_OF_THE_DAY_REPEAT_DAYS",
+    "SPAM_MESSAGE_LIMIT",
+    "SPAM_MUTE_SECS",
+    "SPAM_SAME_ARTIST_LIMIT",
+    "SPAM_SAME_TRACK_LIMIT",
+    "SPAM_WINDOW_SECS",
+    "SPOTIFY_CACHE_SIZE",
+    "SPOTIFY_CACHE_TTL_SECS",
+    "SPOTIFY_RETRY_ATTEMPTS",
+    "SPOTIFY_RETRY_DEADLINE_SECS",
+    "TELEGRAM_ANNOUNCE_CHANNEL_ID",
+    "TWITCH_ANNOUNCE_CHANNEL_ID",
+];
+// Poll intervals, which can't be zero
+const INTERVAL_SETTINGS: &[&str] = &[
+    "FOLLOW_POLL_INTERVAL_SECS",
+    "NEW_RELEASES_POLL_INTERVAL_SECS",
+];
+const BOOL_SETTINGS: &[&str] = &[
+    "ATTACH_PREVIEWS",
+    "DISCORD_ENABLED",
+    "DRY_RUN",
+    "FOLLOW_ANNOUNCEMENT_PING",
+    "MESSAGE_CONTENT_INTENT",
+    "SELFTEST_ON_STARTUP",
+    "SONGLINK_ENABLED",
+    "UNSUPPORTED_LINK_FEEDBACK",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The bot won't start, or a feature won't run.
+    Error,
+    /// The bot falls back to a default the operator probably didn't want.
+    Warning,
+}
+
+pub struct Problem {
+    pub severity: Severity,
+    pub setting: &'static str,
+    /// What is wrong and what the bot does about it.
+    pub message: String,
+}
+
+/// Every problem with the configuration, rather than only the first.
+#[derive(Default)]
+pub struct Validation {
+    pub problems: Vec<Problem>,
+}
+
+impl Validation {
+    fn add(
+        &mut self,
+        severity: Severity,
+        setting: &'static str,
+        message: String,
+    ) {
+        self.problems.push(Problem {
+            severity,
+            setting,
+            message,
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Error)
+    }
+
+    pub fn log(&self) {
+        for problem in &self.problems {
+            match problem.severity {
+                Severity::Error => {
+                    error!("{}: {}", problem.setting, problem.message)
+                }
+                Severity::Warning => {
+                    warn!("{}: {}", problem.setting, problem.message)
+                }
+            }
+        }
+    }
+}
+
+/// Checks every setting the bot reads, including the ones `env_or` would
+/// quietly replace with their default.
+pub fn validate() -> Validation {
+    let mut validation = Validation::default();
+    let discord_token = Some("DISCORD_TOKEN").filter(|_| discord_enabled());
+    for name in discord_token
+        .into_iter()
+        .chain(REQUIRED_SECRETS.iter().copied())
+    {
+        if secrets::read(name).is_none() {
+            validation.add(
+                Severity::Error,
+                name,
+                format!("Not set; set {name} or {name}_FILE"),
+            );
+        }
+    }
+    for &name in NUMBER_SETTINGS {
+        if let Ok(value) = env::var(name) {
+            if value.parse::<u64>().is_err() {
+                validation.add(
+                    Severity::Warning,
+                    name,
+                    format!(
+                        "{value:?} is not a whole number, using the default"
+                    ),
+                );
+            }
+        }
+    }
+    for &name in INTERVAL_SETTINGS {
+        if let Ok(value) = env::var(name) {
+            if !value.parse::<u64>().is_ok_and(|secs| secs > 0) {
+                validation.add(
+                    Severity::Error,
+                    name,
+                    format!("{value:?} is not a positive number of seconds"),
+                );
+            }
+        }
+    }
+    for &name in BOOL_SETTINGS {
+        if let Ok(value) = env::var(name) {
+            if value.parse::<bool>().is_err() {
+                validation.add(
+                    Severity::Warning,
+                    name,
+                    format!(
+                        "{value:?} is not true or false, using the default"
+                    ),
+                );
+            }
+        }
+    }
+    if let Ok(hour) = env::var("SONG_OF_THE_DAY_HOUR") {
+        if !hour.parse::<u64>().is_ok_and(|hour| hour < 24) {
+            validation.add(
+                Severity::Warning,
+                "SONG_OF_THE_DAY_HOUR",
+                format!("{hour:?} is not an hour from 0 to 23"),
+            );
+        }
+    }
+    if let Ok(mode) = env::var("FEEDBACK_MODE") {
+        if FeedbackMode::from_name(&mode).is_none() {
+            validation.add(
+                Severity::Error,
+                "FEEDBACK_MODE",
+                format!("{mode:?} is not full, reactions or silent"),
+            );
+        }
+    }
+    if let Err(why) = spotify_market() {
+        validation.add(Severity::Error, "SPOTIFY_MARKET", why);
+    }
+    if let Ok(rotation) = env::var("LOG_ROTATION") {
+        if !["daily", "hourly", "size"].contains(&rotation.as_str()) {
+            validation.add(
+                Severity::Warning,
+                "LOG_ROTATION",
+                format!(
+                    "{rotation:?} is not daily, hourly or size, rotating daily"
+                ),
+            );
+        }
+    }
+    validation
+}
@@ -1,86 +1,4264 @@
-use std::env;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use log::{error, info};
+use flexi_logger::LoggerHandle;
+use log::{error, info, warn};
+use rand::seq::{IteratorRandom, SliceRandom};
+use serde_json::Value;
 use serenity::async_trait;
+use serenity::builder::CreateApplicationCommands;
+use serenity::gateway::GatewayError;
+use serenity::http::Http;
+use serenity::model::application::command::{Command, CommandOptionType};
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption,
+};
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::interaction::{
+    Interaction, InteractionResponseType,
+};
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
 use serenity::prelude::*;
+use tokio::signal::unix::{signal, SignalKind};
 use url::Url;
 
+use crate::album_art::AlbumColours;
+use crate::blocklist::Blocklist;
+use crate::chat_bridge::{self, ChatBridge, ChatSource};
+use crate::config::{self, env_or};
+use crate::external_links::{self, ExternalLinks};
+use crate::feedback;
+use crate::feedback::FeedbackMode;
+use crate::http_client;
+use crate::logging;
+use crate::metrics::{CommandMetrics, EndpointStats};
+use crate::moderation::{self, Moderation};
+use crate::permissions::{self, CommandPermissions};
+use crate::playlist_locks::PlaylistLocks;
+use crate::playlist_manager;
+use crate::playlist_manager::{AddOutcome, PlaylistCache};
+use crate::preflight;
+use crate::progress::Progress;
+use crate::quiz::Quiz;
+use crate::quota::{self, SubmissionQuota};
+use crate::secrets;
+use crate::social::Social;
+use crate::songlink::{self, Songlink};
 use crate::spotify_client;
+use crate::spotify_client::{
+    FeedbackKind, PlaylistItem, SpotifyError, TrackInfo,
+};
+use crate::systemd;
+
+const DEFAULT_FOLLOW_POLL_INTERVAL_SECS: u64 = 3600;
+// Keeps track listings comfortably under Discord's message size limit
+const MAX_LISTED_TRACKS: usize = 20;
+// Spotify accepts at most 100 tracks per removal request
+const REMOVAL_BATCH_SIZE: usize = 100;
+const DEFAULT_QUIZ_DURATION_SECS: u64 = 30;
+const MAX_LEADERBOARD_ENTRIES: usize = 10;
+const MAX_LISTED_ERRORS: usize = 5;
+const MAX_LISTED_GENRES: usize = 15;
+const DEFAULT_SONG_OF_THE_DAY_HOUR: u64 = 12;
+const DEFAULT_SONG_OF_THE_DAY_REPEAT_DAYS: usize = 30;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+// Discord's upload limit for servers without boosts
+const MAX_PREVIEW_BYTES: u64 = 8 * 1024 * 1024;
+const DEFAULT_NEW_RELEASES_POLL_INTERVAL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_NEW_RELEASES_TOP_ARTISTS: usize = 10;
+// Custom ID prefix of the button that adds a new release's lead track
+const ADD_RELEASE_ID: &str = "add_release:";
+// Custom ID prefix of the button that adds a quarantined track anyway
+const OVERRIDE_ADD_ID: &str = "override_add:";
+// One "Add anyway" button each, in Discord's limit of five rows of five
+const MAX_QUARANTINED_TRACKS: usize = 15;
+const BUTTONS_PER_ROW: usize = 5;
+// Spotify IDs are base62 and always this long
+const SPOTIFY_ID_LENGTH: usize = 22;
+// Custom ID prefix of the button that adds the Spotify match of a SoundCloud
+// or Bandcamp link
+const ADD_MATCH_ID: &str = "add_match:";
+// Each external link costs a page download and a Spotify search
+const MAX_EXTERNAL_LINKS: usize = 3;
+// Discord cuts button labels off after this many characters
+const MAX_BUTTON_LABEL_CHARS: usize = 80;
+// Custom ID prefix of the buttons under a playlist health report
+const HEALTH_FIX_ID: &str = "health_fix:";
+const DEFAULT_HEALTH_MAX_TRACK_MINUTES: u64 = 15;
+const MAX_HEALTH_EXAMPLES: usize = 3;
+// How far a /discover playlist may be off the requested length
+const DISCOVER_DURATION_TOLERANCE_MINUTES: u64 = 5;
+// Each top artist costs a search and a playlist fetch
+const DISCOVER_EDITORIAL_ARTISTS: usize = 5;
+// Keeps the duration fitting table small
+const MAX_DISCOVER_MINUTES: u64 = 600;
+// Event timers are only kept in memory, so long events rarely survive
+const MAX_EVENT_DAYS: u64 = 30;
+// Application flags for the message content intent, from Discord's docs;
+// serenity's constants for these are off by one bit
+const MESSAGE_CONTENT_FLAG: u64 = 1 << 18;
+const MESSAGE_CONTENT_LIMITED_FLAG: u64 = 1 << 19;
+const DEFAULT_MILESTONE_STEP: usize = 100;
+// Discord rejects messages longer than this many characters
+const MAX_MESSAGE_CHARS: usize = 2000;
+// Gold
+const MILESTONE_COLOUR: u32 = 0xF1C40F;
+
+/// An external playlist an admin asked the bot to watch for new tracks.
+struct FollowedPlaylist {
+    name: String,
+    channel_id: ChannelId,
+    /// The server that followed the playlist, whose blocklist imports go
+    /// through.
+    guild_id: Option<GuildId>,
+    import: bool,
+    snapshot_id: String,
+    track_uris: HashSet<String>,
+}
+
+type FollowedPlaylists = Arc<Mutex<HashMap<String, FollowedPlaylist>>>;
+type Blocklists = Arc<Mutex<HashMap<GuildId, Blocklist>>>;
+
+/// A temporary playlist that takes the links posted in one channel until it
+/// expires.
+struct EventPlaylist {
+    name: String,
+    playlist_id: String,
+    track_uris: HashSet<String>,
+}
+
+type EventPlaylists = Arc<Mutex<HashMap<ChannelId, EventPlaylist>>>;
+
+/// A live listening session, during which links posted in its channel are
+/// queued for playback on the bot's Spotify account.
+struct ListeningSession {
+    name: String,
+    host: UserId,
+    /// Queued tracks in the order they were queued.
+    track_uris: Vec<String>,
+}
 
 struct Handler {
     spotify_client: spotify_client::SpotifyClient,
+    followed_playlists: FollowedPlaylists,
+    events: EventPlaylists,
+    sessions: Mutex<HashMap<ChannelId, ListeningSession>>,
+    background_tasks_started: AtomicBool,
+    quiz: Arc<Mutex<Quiz>>,
+    album_colours: AlbumColours,
+    external_links: ExternalLinks,
+    songlink: Option<Songlink>,
+    social: Option<Social>,
+    feedback_modes: Mutex<HashMap<GuildId, FeedbackMode>>,
+    /// `FEEDBACK_MODE`, for servers that haven't picked a mode.
+    default_feedback_mode: FeedbackMode,
+    playlist_cache: Arc<PlaylistCache>,
+    playlist_locks: PlaylistLocks,
+    blocklists: Blocklists,
+    command_permissions: Mutex<HashMap<GuildId, CommandPermissions>>,
+    submission_quota: SubmissionQuota,
+    moderation: Moderation,
+    command_metrics: CommandMetrics,
+    /// Track URIs seen by the last `/admin health`, to report drift.
+    last_health_scan: Mutex<Option<HashSet<String>>>,
+    /// The pinned reply to the latest `/discover` in each channel.
+    discovery_pins: Mutex<HashMap<ChannelId, MessageId>>,
+    logger: LoggerHandle,
+    /// Chats other than Discord, handed to their bridges once connected.
+    chat_sources: Mutex<Vec<Box<dyn ChatSource>>>,
 }
 
-impl Default for Handler {
-    fn default() -> Handler {
-        Handler {
-            spotify_client: spotify_client::SpotifyClient::new(),
+/// A slash command's reply. Errors are only shown to the member who ran the
+/// command, so typos and refusals don't clutter the channel.
+type CommandResult = Result<String, String>;
+
+impl Handler {
+    fn new(
+        http_client: reqwest::blocking::Client,
+        logger: LoggerHandle,
+    ) -> Handler {
+        let market = config::spotify_market().unwrap_or_default();
+        let mut spotify_client =
+            spotify_client::SpotifyClient::new(http_client.clone(), market);
+        // Load the playlist before connecting, so the first link posted after
+        // a restart doesn't wait for every page of it
+        if let Err(why) = preflight::check_playlist_access(
+            &mut spotify_client,
+            spotify_client::PLAYLIST_ID,
+        ) {
+            error!("{}", why);
+        }
+        let playlist_cache = PlaylistCache::new(spotify_client::PLAYLIST_ID);
+        if let Err(why) = playlist_cache.refresh(&mut spotify_client) {
+            error!("Could not load the collaborative playlist: {:?}", why);
+        }
+        let chat_sources = chat_bridge::sources_from_env(&http_client);
+        Handler {
+            spotify_client,
+            followed_playlists: Arc::new(Mutex::new(HashMap::new())),
+            events: Arc::new(Mutex::new(HashMap::new())),
+            sessions: Mutex::new(HashMap::new()),
+            background_tasks_started: AtomicBool::new(false),
+            quiz: Arc::new(Mutex::new(Quiz::default())),
+            album_colours: AlbumColours::new(http_client.clone()),
+            external_links: ExternalLinks::new(http_client.clone()),
+            songlink: Songlink::from_env(http_client.clone()),
+            social: Social::from_env(http_client),
+            feedback_modes: Mutex::new(HashMap::new()),
+            default_feedback_mode: FeedbackMode::default_from_env(),
+            playlist_cache: Arc::new(playlist_cache),
+            playlist_locks: PlaylistLocks::default(),
+            blocklists: Arc::new(Mutex::new(HashMap::new())),
+            command_permissions: Mutex::new(HashMap::new()),
+            submission_quota: SubmissionQuota::default(),
+            moderation: Moderation::default(),
+            command_metrics: CommandMetrics::default(),
+            last_health_scan: Mutex::new(None),
+            discovery_pins: Mutex::new(HashMap::new()),
+            logger,
+            chat_sources: Mutex::new(chat_sources),
+        }
+    }
+
+    async fn run_admin_command(
+        &self,
+        command: &ApplicationCommandInteraction,
+    ) -> CommandResult {
+        let subcommand = match command.data.options.first() {
+            Some(subcommand) => subcommand,
+            None => return Err("Missing admin subcommand".to_string()),
+        };
+        match subcommand.name.as_str() {
+            "follow" => {
+                self.follow_playlist(
+                    command.channel_id,
+                    command.guild_id,
+                    &subcommand.options,
+                )
+                .await
+            }
+            "unfollow" => self.unfollow_playlist(&subcommand.options).await,
+            "dedupe" => self.dedupe_playlist(&subcommand.options).await,
+            "sort" => self.sort_playlist(&subcommand.options).await,
+            "feedback" => {
+                self.set_feedback_mode(command.guild_id, &subcommand.options)
+                    .await
+            }
+            "block" => self.update_blocklist(command, subcommand, true).await,
+            "unblock" => {
+                self.update_blocklist(command, subcommand, false).await
+            }
+            "filters" => Ok(self.list_filters(command.guild_id).await),
+            "usage" => Ok(self.command_usage()),
+            "log" => self.set_log_filter(command, &subcommand.options),
+            "permissions" => {
+                self.update_permissions(command, &subcommand.options).await
+            }
+            _ => Err(format!("Unknown admin subcommand `{}`", subcommand.name)),
+        }
+    }
+
+    async fn follow_playlist(
+        &self,
+        channel_id: ChannelId,
+        guild_id: Option<GuildId>,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let playlist_id = match option_value(options, "playlist")
+            .and_then(Value::as_str)
+            .and_then(parse_playlist_id)
+        {
+            Some(playlist_id) => playlist_id,
+            None => {
+                return Err("That doesn't look like a Spotify playlist link"
+                    .to_string())
+            }
+        };
+        let import = option_value(options, "import")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+
+        let mut spotify_client = self.spotify_client.clone();
+        let snapshot = match spotify_client.get_playlist_snapshot(&playlist_id)
+        {
+            Ok(snapshot) => snapshot,
+            Err(why) => {
+                error!("Could not fetch playlist {}: {:?}", playlist_id, why);
+                return Err(
+                    "Could not find that playlist on Spotify".to_string()
+                );
+            }
+        };
+        let tracks = match spotify_client.get_playlist_tracks(&playlist_id) {
+            Ok(tracks) => tracks,
+            Err(why) => {
+                error!("Could not fetch playlist {}: {:?}", playlist_id, why);
+                return Err(
+                    "Could not read the tracks of that playlist".to_string()
+                );
+            }
+        };
+
+        let reply = format!(
+            "Now following **{}**; new tracks will be announced here{}",
+            snapshot.name,
+            if import {
+                " and added to the collaborative playlist"
+            } else {
+                ""
+            }
+        );
+        self.followed_playlists.lock().await.insert(
+            playlist_id,
+            FollowedPlaylist {
+                name: snapshot.name,
+                channel_id,
+                guild_id,
+                import,
+                snapshot_id: snapshot.snapshot_id,
+                track_uris: tracks.into_iter().map(|track| track.uri).collect(),
+            },
+        );
+        Ok(reply)
+    }
+
+    /// Creates a playlist for an event and routes links posted in the chosen
+    /// channel, or the current one, to it until it expires.
+    async fn create_event(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+    ) -> CommandResult {
+        let options = match command.data.options.first() {
+            Some(subcommand) if subcommand.name == "create" => {
+                &subcommand.options
+            }
+            _ => return Err("Unknown event subcommand".to_string()),
+        };
+        let name = match option_value(options, "name").and_then(Value::as_str) {
+            Some(name) => name.trim().to_string(),
+            None => return Err("Missing event name".to_string()),
+        };
+        let days = option_value(options, "duration")
+            .and_then(Value::as_u64)
+            .unwrap_or(1);
+        let channel_id = option_value(options, "channel")
+            .and_then(Value::as_str)
+            .and_then(|id| id.parse().ok())
+            .map(ChannelId)
+            .unwrap_or(command.channel_id);
+        if let Some(event) = self.events.lock().await.get(&channel_id) {
+            return Err(format!(
+                "{} already collects links for **{}**",
+                channel_id.mention(),
+                event.name
+            ));
+        }
+
+        let mut spotify_client = self.spotify_client.clone();
+        let description =
+            format!("Tracks shared during {name}, collected by the bot");
+        let playlist_id =
+            match spotify_client.create_playlist(&name, &description) {
+                Ok(playlist_id) => playlist_id,
+                Err(why) => {
+                    error!("Could not create event playlist: {}", why);
+                    return Err(describe_spotify_error(
+                        &why,
+                        "Could not create the event playlist",
+                        "Could not create the event playlist",
+                    ));
+                }
+            };
+        info!(
+            "{} created event {} in channel {} for {} days",
+            command.user.tag(),
+            name,
+            channel_id,
+            days
+        );
+        self.events.lock().await.insert(
+            channel_id,
+            EventPlaylist {
+                name: name.clone(),
+                playlist_id: playlist_id.clone(),
+                track_uris: HashSet::new(),
+            },
+        );
+        tokio::spawn(end_event(
+            ctx.clone(),
+            spotify_client,
+            self.events.clone(),
+            channel_id,
+            playlist_id.clone(),
+            Duration::from_secs(days * SECS_PER_DAY),
+        ));
+        Ok(format!(
+            "Links posted in {} for the next {} day{} go to **{}**: {}",
+            channel_id.mention(),
+            days,
+            if days == 1 { "" } else { "s" },
+            name,
+            spotify_client::playlist_url(&playlist_id)
+        ))
+    }
+
+    /// Adds the tracks linked in a message to the event playlist of its
+    /// channel instead of the collaborative playlist. Returns how many were
+    /// added.
+    async fn add_to_event(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        track_ids: &[String],
+    ) -> usize {
+        let mode = self.feedback_mode(msg).await;
+        let blocklist = self.blocklist(msg.guild_id).await;
+        let mut spotify_client = self.spotify_client.clone();
+        let mut seen = HashSet::new();
+        let mut lines = Vec::new();
+        let (mut added, mut duplicates, mut failed) = (0, 0, 0);
+        for track_id in track_ids {
+            if !seen.insert(track_id) {
+                continue;
+            }
+            let track = match spotify_client.get_track(track_id) {
+                Ok(track) => track,
+                Err(why) => {
+                    error!("Could not fetch track {}: {}", track_id, why);
+                    failed += 1;
+                    let reason = describe_spotify_error(
+                        &why,
+                        "Could not find that track on Spotify",
+                        "Could not add that track to the playlist",
+                    );
+                    lines.push(format!("❌ `{track_id}`: {reason}"));
+                    continue;
+                }
+            };
+            if let Some(artist) = blocklist.blocked_artist(&track) {
+                failed += 1;
+                let reason = format!("{artist} is blocked in this server");
+                quarantine(ctx, msg, &[track_id], &reason).await;
+                lines.push(format!("⛔ **{}** — {}", track.name, reason));
+                continue;
+            }
+            // The event may have ended while earlier tracks were added
+            let playlist_id =
+                match self.events.lock().await.get(&msg.channel_id) {
+                    Some(event) if event.track_uris.contains(&track.uri) => {
+                        duplicates += 1;
+                        lines.push(format!(
+                            "🔁 **{}** is already in the event playlist",
+                            track.name
+                        ));
+                        continue;
+                    }
+                    Some(event) => event.playlist_id.clone(),
+                    None => break,
+                };
+            match spotify_client.add_to_playlist(&playlist_id, &track.uri) {
+                Ok(_) => {
+                    added += 1;
+                    lines.push(format!(
+                        "✅ **{}** — {}",
+                        track.name,
+                        track.artists.join(", ")
+                    ));
+                    if let Some(event) =
+                        self.events.lock().await.get_mut(&msg.channel_id)
+                    {
+                        event.track_uris.insert(track.uri);
+                    }
+                }
+                Err(why) => {
+                    error!("Could not add track {}: {}", track_id, why);
+                    failed += 1;
+                    lines.push(format!(
+                        "❌ **{}**: {}",
+                        track.name,
+                        describe_spotify_error(
+                            &why,
+                            "Could not find the event playlist",
+                            "Could not add that track to the event playlist",
+                        )
+                    ));
+                }
+            }
+        }
+
+        lines.truncate(MAX_LISTED_TRACKS);
+        let reactions = [
+            (added > 0, feedback::ADDED_REACTION),
+            (duplicates > 0, feedback::DUPLICATE_REACTION),
+            (failed > 0, feedback::FAILED_REACTION),
+        ];
+        let summary = format!("Added {added} to the event playlist");
+        feedback::respond_summary(
+            ctx,
+            msg,
+            mode,
+            &reactions,
+            summary,
+            lines.join("\n"),
+        )
+        .await;
+        added
+    }
+
+    async fn run_session_command(
+        &self,
+        command: &ApplicationCommandInteraction,
+    ) -> CommandResult {
+        let subcommand = match command.data.options.first() {
+            Some(subcommand) => subcommand,
+            None => return Err("Missing session subcommand".to_string()),
+        };
+        match subcommand.name.as_str() {
+            "start" => {
+                let name = option_value(&subcommand.options, "name")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Listening session")
+                    .to_string();
+                self.start_session(command, name).await
+            }
+            "end" => self.end_session(command).await,
+            _ => {
+                Err(format!("Unknown session subcommand `{}`", subcommand.name))
+            }
+        }
+    }
+
+    async fn start_session(
+        &self,
+        command: &ApplicationCommandInteraction,
+        name: String,
+    ) -> CommandResult {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(session) = sessions.get(&command.channel_id) {
+            return Err(format!(
+                "**{}** is already running here, hosted by {}",
+                session.name,
+                session.host.mention()
+            ));
+        }
+        info!("{} started session {}", command.user.tag(), name);
+        let reply = format!(
+            "**{name}** has started! Links posted here are queued on the \
+             bot's Spotify player until {} runs `/session end`",
+            command.user.mention()
+        );
+        sessions.insert(
+            command.channel_id,
+            ListeningSession {
+                name,
+                host: command.user.id,
+                track_uris: Vec::new(),
+            },
+        );
+        Ok(reply)
+    }
+
+    /// Ends the channel's session and saves what was queued in a recap
+    /// playlist. Only the host or a server manager can end a session.
+    async fn end_session(
+        &self,
+        command: &ApplicationCommandInteraction,
+    ) -> CommandResult {
+        let mut sessions = self.sessions.lock().await;
+        let session = match sessions.get(&command.channel_id) {
+            Some(session) => session,
+            None => return Err("There is no session running here".to_string()),
+        };
+        let is_manager = command
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| permissions.manage_guild());
+        if session.host != command.user.id && !is_manager {
+            return Err(format!(
+                "Only {} or a server manager can end **{}**",
+                session.host.mention(),
+                session.name
+            ));
+        }
+        let session = match sessions.remove(&command.channel_id) {
+            Some(session) => session,
+            None => return Err("There is no session running here".to_string()),
+        };
+        drop(sessions);
+        info!("{} ended session {}", command.user.tag(), session.name);
+        if session.track_uris.is_empty() {
+            return Ok(format!(
+                "**{}** has ended, nothing was queued",
+                session.name
+            ));
+        }
+
+        let mut spotify_client = self.spotify_client.clone();
+        let description = format!(
+            "The {} tracks queued during {}",
+            session.track_uris.len(),
+            session.name
+        );
+        let recap = spotify_client
+            .create_playlist(&session.name, &description)
+            .and_then(|playlist_id| {
+                spotify_client
+                    .add_tracks_to_playlist(&playlist_id, &session.track_uris)
+                    .map(|_| playlist_id)
+            });
+        // The session is over either way, so both are news for the channel
+        Ok(match recap {
+            Ok(playlist_id) => format!(
+                "**{}** has ended after {} tracks, relive it at {}",
+                session.name,
+                session.track_uris.len(),
+                spotify_client::playlist_url(&playlist_id)
+            ),
+            Err(why) => {
+                error!("Could not create session recap: {}", why);
+                format!(
+                    "**{}** has ended after {} tracks, but the recap \
+                     playlist could not be created",
+                    session.name,
+                    session.track_uris.len()
+                )
+            }
+        })
+    }
+
+    /// Queues the tracks linked in a message for the channel's session, in
+    /// the order they were linked. Returns how many were queued.
+    async fn queue_for_session(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        track_ids: &[String],
+    ) -> usize {
+        let mode = self.feedback_mode(msg).await;
+        let blocklist = self.blocklist(msg.guild_id).await;
+        let mut spotify_client = self.spotify_client.clone();
+        // Held throughout, so tracks from messages posted close together
+        // are queued one message after another
+        let mut sessions = self.sessions.lock().await;
+        let session = match sessions.get_mut(&msg.channel_id) {
+            Some(session) => session,
+            None => return 0,
+        };
+        let mut lines = Vec::new();
+        let (mut queued, mut failed) = (0, 0);
+        for track_id in track_ids {
+            let queued_track = match spotify_client.get_track(track_id) {
+                Ok(track) => match blocklist.blocked_artist(&track) {
+                    Some(artist) => {
+                        failed += 1;
+                        lines.push(format!(
+                            "⛔ **{}** — {} is blocked in this server",
+                            track.name, artist
+                        ));
+                        continue;
+                    }
+                    None => {
+                        spotify_client.add_to_queue(&track.uri).map(|()| track)
+                    }
+                },
+                Err(why) => Err(why),
+            };
+            match queued_track {
+                Ok(track) => {
+                    queued += 1;
+                    lines.push(format!(
+                        "🎶 **{}** — {}",
+                        track.name,
+                        track.artists.join(", ")
+                    ));
+                    session.track_uris.push(track.uri);
+                }
+                Err(why) => {
+                    error!("Could not queue track {}: {}", track_id, why);
+                    failed += 1;
+                    let reason = describe_spotify_error(
+                        &why,
+                        "Could not find that track, or nothing is playing",
+                        "Could not queue that track",
+                    );
+                    lines.push(format!("❌ `{track_id}`: {reason}"));
+                }
+            }
+        }
+        drop(sessions);
+
+        lines.truncate(MAX_LISTED_TRACKS);
+        let reactions = [
+            (queued > 0, feedback::ADDED_REACTION),
+            (failed > 0, feedback::FAILED_REACTION),
+        ];
+        let summary = format!("Queued {queued} for the session");
+        feedback::respond_summary(
+            ctx,
+            msg,
+            mode,
+            &reactions,
+            summary,
+            lines.join("\n"),
+        )
+        .await;
+        queued
+    }
+
+    /// Fills `DISCOVER_PLAYLIST_ID` with the candidate tracks whose tempo is
+    /// in the requested range, slowest first so the playlist builds up.
+    /// Candidates are the collaborative playlist's tracks, or with the
+    /// editorial source, tracks from "This Is" playlists it doesn't have yet.
+    /// With `minutes`, a random selection lasting about that long is used.
+    async fn discover_by_tempo(
+        &self,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let (min_bpm, max_bpm) = match option_value(options, "bpm")
+            .and_then(Value::as_str)
+            .and_then(parse_bpm_range)
+        {
+            Some(range) => range,
+            None => {
+                return Err("Give a tempo range such as `120-140`".to_string())
+            }
+        };
+        let minutes = option_value(options, "minutes").and_then(Value::as_u64);
+        let editorial = option_value(options, "source")
+            .and_then(Value::as_str)
+            .is_some_and(|source| source == "editorial");
+        let playlist_id = env_or("DISCOVER_PLAYLIST_ID", String::new());
+        if playlist_id.is_empty() {
+            return Err(
+                "No playlist to write to, set DISCOVER_PLAYLIST_ID".to_string()
+            );
+        }
+
+        // Overlapping runs would interleave their writes
+        let _lock = self.playlist_locks.lock(&playlist_id).await;
+        let mut spotify_client = self.spotify_client.clone();
+        let tracks = match spotify_client
+            .get_playlist_tracks(spotify_client::PLAYLIST_ID)
+        {
+            Ok(tracks) => tracks,
+            Err(why) => {
+                error!("Could not fetch the playlist: {:?}", why);
+                return Err(
+                    "Could not read the collaborative playlist".to_string()
+                );
+            }
+        };
+        let tracks = if editorial {
+            match playlist_manager::editorial_candidates(
+                &mut spotify_client,
+                &tracks,
+                DISCOVER_EDITORIAL_ARTISTS,
+            ) {
+                Ok(candidates) => candidates,
+                Err(why) => {
+                    error!("Could not mine editorial playlists: {:?}", why);
+                    return Err(
+                        "Could not search Spotify's editorial playlists"
+                            .to_string(),
+                    );
+                }
+            }
+        } else {
+            tracks
+        };
+        let mut seen = HashSet::new();
+        let tracks: Vec<(String, &TrackInfo)> = tracks
+            .iter()
+            .filter_map(|track| {
+                let id = track.uri.strip_prefix("spotify:track:")?;
+                seen.insert(id).then(|| (id.to_string(), track))
+            })
+            .collect();
+        let track_ids: Vec<String> =
+            tracks.iter().map(|(id, _)| id.clone()).collect();
+        let tempos = match spotify_client.get_track_tempos(&track_ids) {
+            Ok(tempos) => tempos,
+            Err(why) => {
+                error!("Could not fetch track tempos: {}", why);
+                return Err(describe_spotify_error(
+                    &why,
+                    "Spotify has no tempo data for these tracks",
+                    "Could not look up the tracks' tempos",
+                ));
+            }
+        };
+        let mut matches: Vec<(f64, &TrackInfo)> = tracks
+            .into_iter()
+            .filter_map(|(id, track)| {
+                let tempo = *tempos.get(&id)?;
+                (min_bpm..=max_bpm)
+                    .contains(&tempo)
+                    .then_some((tempo, track))
+            })
+            .collect();
+        if matches.is_empty() {
+            return Err(format!(
+                "No candidate tracks are between {min_bpm} and {max_bpm} BPM"
+            ));
+        }
+        if let Some(minutes) = minutes {
+            // Shuffled, so repeat runs pick different combinations
+            matches.shuffle(&mut rand::thread_rng());
+            let durations: Vec<u64> =
+                matches.iter().map(|(_, track)| track.duration_ms).collect();
+            let picked = match playlist_manager::fit_duration(
+                &durations,
+                minutes * 60 * 1000,
+                DISCOVER_DURATION_TOLERANCE_MINUTES * 60 * 1000,
+            ) {
+                Some(picked) => picked,
+                None => {
+                    return Err(format!(
+                        "The tracks between {min_bpm} and {max_bpm} BPM \
+                         can't fill {minutes}±\
+                         {DISCOVER_DURATION_TOLERANCE_MINUTES} minutes"
+                    ))
+                }
+            };
+            matches = picked.into_iter().map(|index| matches[index]).collect();
+        }
+        matches.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let minutes_total = matches
+            .iter()
+            .map(|(_, track)| track.duration_ms)
+            .sum::<u64>()
+            / 60_000;
+        let uris: Vec<String> =
+            matches.iter().map(|(_, track)| track.uri.clone()).collect();
+        match spotify_client.replace_playlist_tracks(&playlist_id, &uris) {
+            Ok(()) => {
+                let mut reply = format!(
+                    "Wrote {} tracks between {} and {} BPM, {} minutes in \
+                     total, to {}",
+                    uris.len(),
+                    min_bpm,
+                    max_bpm,
+                    minutes_total,
+                    spotify_client::playlist_url(&playlist_id)
+                );
+                for (number, (tempo, track)) in matches.iter().enumerate() {
+                    reply.push_str(&format!(
+                        "\n{}. {} — {} ({}, {:.0} BPM)",
+                        number + 1,
+                        track.name,
+                        track.artists.join(", "),
+                        format_duration(track.duration_ms),
+                        tempo
+                    ));
+                }
+                if let Some(social) = &self.social {
+                    let mut popular: Vec<&TrackInfo> =
+                        matches.iter().map(|(_, track)| *track).collect();
+                    popular.sort_by_key(|track| Reverse(track.popularity));
+                    let lines: Vec<String> = popular
+                        .iter()
+                        .map(|track| {
+                            format!(
+                                "{} — {}",
+                                track.name,
+                                track.artists.join(", ")
+                            )
+                        })
+                        .collect();
+                    social.post(
+                        &format!(
+                            "New discovery playlist: {} tracks between {} \
+                             and {} BPM",
+                            uris.len(),
+                            min_bpm,
+                            max_bpm
+                        ),
+                        &spotify_client::playlist_url(&playlist_id),
+                        &lines,
+                    );
+                }
+                Ok(reply)
+            }
+            Err(why) => {
+                error!("Could not write tempo playlist: {}", why);
+                Err(describe_spotify_error(
+                    &why,
+                    "Could not find the playlist in DISCOVER_PLAYLIST_ID",
+                    "Could not write the tempo playlist",
+                ))
+            }
+        }
+    }
+
+    async fn unfollow_playlist(
+        &self,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let playlist_id = match option_value(options, "playlist")
+            .and_then(Value::as_str)
+            .and_then(parse_playlist_id)
+        {
+            Some(playlist_id) => playlist_id,
+            None => {
+                return Err("That doesn't look like a Spotify playlist link"
+                    .to_string())
+            }
+        };
+        match self.followed_playlists.lock().await.remove(&playlist_id) {
+            Some(followed) => {
+                Ok(format!("Stopped following **{}**", followed.name))
+            }
+            None => Err("That playlist is not being followed".to_string()),
+        }
+    }
+
+    async fn dedupe_playlist(
+        &self,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let confirm = option_value(options, "confirm")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        self.dedupe(confirm).await
+    }
+
+    /// Lists the collaborative playlist's duplicates, or removes them once
+    /// `confirm`ed.
+    async fn dedupe(&self, confirm: bool) -> CommandResult {
+        let mut spotify_client = self.spotify_client.clone();
+        let playlist_id = spotify_client::PLAYLIST_ID;
+        // Positions are only valid until another command changes the playlist
+        let _lock = self.playlist_locks.lock(playlist_id).await;
+        let (snapshot_id, duplicates) = read_at_snapshot(
+            &mut spotify_client,
+            playlist_id,
+            |spotify_client| {
+                playlist_manager::find_duplicates(
+                    spotify_client.playlist_items_iter(playlist_id),
+                )
+            },
+        )?;
+        if duplicates.is_empty() {
+            return Ok(
+                "No duplicates found in the collaborative playlist".to_string()
+            );
+        }
+
+        if !confirm {
+            let mut preview = format!("Found {} duplicates:", duplicates.len());
+            for duplicate in duplicates.iter().take(MAX_LISTED_TRACKS) {
+                preview.push_str(&format!(
+                    "\n• #{} {} — {} ({} as {})",
+                    duplicate.position + 1,
+                    duplicate.track.name,
+                    duplicate.track.artists.join(", "),
+                    duplicate.kind.describe(),
+                    duplicate.original.name,
+                ));
+            }
+            if duplicates.len() > MAX_LISTED_TRACKS {
+                preview.push_str(&format!(
+                    "\n…and {} more",
+                    duplicates.len() - MAX_LISTED_TRACKS
+                ));
+            }
+            preview
+                .push_str("\nRun `/admin dedupe confirm:true` to remove them");
+            return Ok(preview);
+        }
+
+        let removals = duplicates
+            .iter()
+            .map(|duplicate| (duplicate.track.uri.clone(), duplicate.position))
+            .collect();
+        remove_from_playlist(
+            &spotify_client,
+            snapshot_id,
+            removals,
+            "duplicates",
+        )
+    }
+
+    async fn remove_unplayable(&self) -> CommandResult {
+        let mut spotify_client = self.spotify_client.clone();
+        let playlist_id = spotify_client::PLAYLIST_ID;
+        let _lock = self.playlist_locks.lock(playlist_id).await;
+        let (snapshot_id, items) = read_at_snapshot(
+            &mut spotify_client,
+            playlist_id,
+            |spotify_client| spotify_client.get_playlist_items(playlist_id),
+        )?;
+        let removals: Vec<(String, usize)> = items
+            .iter()
+            .enumerate()
+            .filter_map(|(position, item)| {
+                let track = item.track.as_ref()?;
+                (track.is_playable == Some(false))
+                    .then(|| (track.uri.clone(), position))
+            })
+            .collect();
+        if removals.is_empty() {
+            return Ok("No unplayable tracks in the collaborative playlist"
+                .to_string());
+        }
+        remove_from_playlist(
+            &spotify_client,
+            snapshot_id,
+            removals,
+            "unplayable tracks",
+        )
+    }
+
+    /// Scans the collaborative playlist for problems and suggests fixes,
+    /// with buttons for the ones the bot can make itself.
+    async fn playlist_health(&self) -> (String, Vec<(&'static str, String)>) {
+        let mut spotify_client = self.spotify_client.clone();
+        let playlist_id = spotify_client::PLAYLIST_ID;
+        let items = match spotify_client.get_playlist_items(playlist_id) {
+            Ok(items) => items,
+            Err(why) => {
+                error!("Could not fetch playlist {}: {:?}", playlist_id, why);
+                let content = "Could not read the collaborative playlist";
+                return (content.to_string(), Vec::new());
+            }
+        };
+        let max_minutes = env_or(
+            "HEALTH_MAX_TRACK_MINUTES",
+            DEFAULT_HEALTH_MAX_TRACK_MINUTES,
+        );
+        let report =
+            playlist_manager::health_report(&items, max_minutes * 60 * 1000);
+
+        let uris: HashSet<String> = items
+            .iter()
+            .filter_map(|item| item.track.as_ref())
+            .map(|track| track.uri.clone())
+            .collect();
+        let previous = self.last_health_scan.lock().await.replace(uris.clone());
+        let mut lines = vec![format!("Health of {} tracks", items.len())];
+        match previous {
+            Some(previous) => lines.push(format!(
+                "Since the last report: {} added, {} removed",
+                uris.difference(&previous).count(),
+                previous.difference(&uris).count()
+            )),
+            None => {
+                lines.push("First report since the bot started".to_string())
+            }
+        }
+        if report.is_healthy() {
+            lines.push("✅ No problems found".to_string());
+            return (lines.join("\n"), Vec::new());
+        }
+
+        let mut fixes = Vec::new();
+        if !report.missing.is_empty() {
+            lines.push(format!(
+                "⚠️ {} no longer on Spotify, at {}; remove them in the \
+                 Spotify app",
+                report.missing.len(),
+                report
+                    .missing
+                    .iter()
+                    .take(MAX_HEALTH_EXAMPLES)
+                    .map(|position| format!("#{}", position + 1))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        if !report.local.is_empty() {
+            lines.push(format!(
+                "📁 {} local files, which only play for whoever added them, \
+                 at {}",
+                report.local.len(),
+                report
+                    .local
+                    .iter()
+                    .take(MAX_HEALTH_EXAMPLES)
+                    .map(|position| format!("#{}", position + 1))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        if !report.unplayable.is_empty() {
+            lines.push(format!(
+                "🚫 {} can't be played in the bot's market",
+                report.unplayable.len()
+            ));
+            lines.extend(health_examples(&report.unplayable));
+            fixes.push((
+                "Remove unplayable",
+                format!("{HEALTH_FIX_ID}unplayable"),
+            ));
+        }
+        if !report.duplicates.is_empty() {
+            lines.push(format!("🔁 {} duplicates", report.duplicates.len()));
+            let duplicates: Vec<(usize, TrackInfo)> = report
+                .duplicates
+                .iter()
+                .take(MAX_HEALTH_EXAMPLES)
+                .map(|duplicate| (duplicate.position, duplicate.track.clone()))
+                .collect();
+            lines.extend(health_examples(&duplicates));
+            fixes.push((
+                "Remove duplicates",
+                format!("{HEALTH_FIX_ID}duplicates"),
+            ));
+        }
+        if !report.unpopular.is_empty() {
+            lines.push(format!(
+                "📉 {} with zero popularity, often re-uploads of a better \
+                 known release",
+                report.unpopular.len()
+            ));
+            lines.extend(health_examples(&report.unpopular));
+        }
+        if !report.overlong.is_empty() {
+            lines.push(format!(
+                "⏱️ {} longer than {} minutes",
+                report.overlong.len(),
+                max_minutes
+            ));
+            lines.extend(health_examples(&report.overlong));
+        }
+        (lines.join("\n"), fixes)
+    }
+
+    async fn sort_playlist(
+        &self,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let key = match option_value(options, "by")
+            .and_then(Value::as_str)
+            .and_then(playlist_manager::SortKey::from_name)
+        {
+            Some(key) => key,
+            None => return Err("Unknown sort order".to_string()),
+        };
+
+        let mut spotify_client = self.spotify_client.clone();
+        let playlist_id = spotify_client::PLAYLIST_ID;
+        let _lock = self.playlist_locks.lock(playlist_id).await;
+        let (mut snapshot_id, items) = read_at_snapshot(
+            &mut spotify_client,
+            playlist_id,
+            |spotify_client| spotify_client.get_playlist_items(playlist_id),
+        )?;
+
+        let moves = playlist_manager::plan_sort(&items, key);
+        for (applied, range_move) in moves.iter().enumerate() {
+            match spotify_client.reorder_playlist_tracks(
+                playlist_id,
+                &snapshot_id,
+                range_move.range_start,
+                range_move.range_length,
+                range_move.insert_before,
+            ) {
+                Ok(new_snapshot_id) => snapshot_id = new_snapshot_id,
+                Err(why) => {
+                    error!("Could not reorder playlist: {:?}", why);
+                    return Err(format!(
+                        "Spotify rejected the reorder after {} of {} moves; \
+                         run the sort again to finish",
+                        applied,
+                        moves.len()
+                    ));
+                }
+            }
+        }
+        Ok(format!(
+            "Sorted {} tracks in {} moves",
+            items.len(),
+            moves.len()
+        ))
+    }
+
+    async fn set_feedback_mode(
+        &self,
+        guild_id: Option<GuildId>,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let guild_id = match guild_id {
+            Some(guild_id) => guild_id,
+            None => return Err("Feedback modes are set per server".to_string()),
+        };
+        let mode = match option_value(options, "mode")
+            .and_then(Value::as_str)
+            .and_then(FeedbackMode::from_name)
+        {
+            Some(mode) => mode,
+            None => return Err("Unknown feedback mode".to_string()),
+        };
+        self.feedback_modes.lock().await.insert(guild_id, mode);
+        Ok(match mode {
+            FeedbackMode::Full => "I'll reply to links with details",
+            FeedbackMode::Reactions => "I'll only react to links",
+            FeedbackMode::Silent => "I'll add links without saying anything",
+        }
+        .to_string())
+    }
+
+    /// Swaps the log filter of the running bot, such as
+    /// `info,sonic::spotify_client=debug` while diagnosing Spotify requests.
+    /// Without a filter, goes back to the one it started with.
+    fn set_log_filter(
+        &self,
+        command: &ApplicationCommandInteraction,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let filter = option_value(options, "filter")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .unwrap_or_else(logging::startup_spec);
+        // Logged before the change, which may filter out info
+        warn!("{} set the log filter to {}", command.user.tag(), filter);
+        match self.logger.parse_new_spec(&filter) {
+            Ok(()) => Ok(format!("Logging with `{filter}`")),
+            Err(why) => {
+                error!("Could not set log filter {}: {:?}", filter, why);
+                Err(format!("`{filter}` is not a valid log filter"))
+            }
+        }
+    }
+
+    /// Requires the given role for a command, or resets the command to its
+    /// default without one. Lists who may run each command either way.
+    async fn update_permissions(
+        &self,
+        command: &ApplicationCommandInteraction,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let guild_id = match command.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Err("Permissions are set per server".to_string()),
+        };
+        let name = option_value(options, "command").and_then(Value::as_str);
+        let role_id = option_value(options, "role")
+            .and_then(Value::as_str)
+            .and_then(|id| id.parse().ok())
+            .map(RoleId);
+        let mut all_permissions = self.command_permissions.lock().await;
+        let permissions = all_permissions.entry(guild_id).or_default();
+        let mut lines = Vec::new();
+        match (name, role_id) {
+            (Some(name), Some(role_id)) => {
+                permissions.require_role(name, role_id);
+                info!(
+                    "{} required role {} for /{} in guild {}",
+                    command.user.tag(),
+                    role_id,
+                    name,
+                    guild_id
+                );
+                lines.push(format!(
+                    "Only {} can now use `/{name}`",
+                    permissions.requirement(name).describe()
+                ));
+            }
+            (Some(name), None) => {
+                if permissions.reset(name) {
+                    info!(
+                        "{} reset the permissions of /{} in guild {}",
+                        command.user.tag(),
+                        name,
+                        guild_id
+                    );
+                }
+                lines.push(format!("`/{name}` is back to its default"));
+            }
+            (None, Some(_)) => {
+                return Err("Pick the command that needs the role".to_string())
+            }
+            (None, None) => {}
+        }
+        lines.push(permissions.describe());
+        Ok(lines.join("\n\n"))
+    }
+
+    /// Blocks or unblocks the artist and member given in the subcommand's
+    /// options. Every change is logged with the admin who made it.
+    async fn update_blocklist(
+        &self,
+        command: &ApplicationCommandInteraction,
+        subcommand: &CommandDataOption,
+        block: bool,
+    ) -> CommandResult {
+        let guild_id = match command.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Err("Blocklists are set per server".to_string()),
+        };
+        let artist = option_value(&subcommand.options, "artist")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|artist| !playlist_manager::normalize(artist).is_empty());
+        let user_id = option_value(&subcommand.options, "user")
+            .and_then(Value::as_str)
+            .and_then(|id| id.parse().ok())
+            .map(UserId);
+        if artist.is_none() && user_id.is_none() {
+            return Err("Give an artist, a member or both".to_string());
+        }
+
+        let verb = if block { "blocked" } else { "unblocked" };
+        let mut blocklists = self.blocklists.lock().await;
+        let blocklist = blocklists.entry(guild_id).or_default();
+        let mut lines = Vec::new();
+        if let Some(artist) = artist {
+            let changed = if block {
+                blocklist.block_artist(artist)
+            } else {
+                blocklist.unblock_artist(artist)
+            };
+            if changed {
+                info!(
+                    "{} {} artist {} in guild {}",
+                    command.user.tag(),
+                    verb,
+                    artist,
+                    guild_id
+                );
+                lines.push(format!("**{artist}** is now {verb}"));
+            } else {
+                lines.push(format!("**{artist}** was already {verb}"));
+            }
+        }
+        if let Some(user_id) = user_id {
+            let changed = if block {
+                blocklist.block_user(user_id)
+            } else {
+                blocklist.unblock_user(user_id)
+            };
+            if changed {
+                info!(
+                    "{} {} user {} in guild {}",
+                    command.user.tag(),
+                    verb,
+                    user_id,
+                    guild_id
+                );
+                lines.push(format!("{} is now {verb}", user_id.mention()));
+            } else {
+                lines.push(format!("{} was already {verb}", user_id.mention()));
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    async fn list_filters(&self, guild_id: Option<GuildId>) -> String {
+        let blocklist = self.blocklist(guild_id).await;
+        if blocklist.is_empty() {
+            return "Nothing is blocked in this server".to_string();
+        }
+        let artists: Vec<String> = blocklist
+            .artists()
+            .map(|artist| format!("**{artist}**"))
+            .collect();
+        let users: Vec<String> = blocklist
+            .users()
+            .map(|user_id| user_id.mention().to_string())
+            .collect();
+        let mut lines = Vec::new();
+        if !artists.is_empty() {
+            lines.push(format!("Blocked artists: {}", artists.join(", ")));
+        }
+        if !users.is_empty() {
+            lines.push(format!("Blocked members: {}", users.join(", ")));
+        }
+        lines.join("\n")
+    }
+
+    async fn command_permissions(
+        &self,
+        guild_id: Option<GuildId>,
+    ) -> CommandPermissions {
+        let all_permissions = self.command_permissions.lock().await;
+        guild_id
+            .and_then(|guild_id| all_permissions.get(&guild_id).cloned())
+            .unwrap_or_default()
+    }
+
+    /// A copy of the server's blocklist, so adding tracks doesn't hold the
+    /// lock across Spotify requests.
+    async fn blocklist(&self, guild_id: Option<GuildId>) -> Blocklist {
+        let blocklists = self.blocklists.lock().await;
+        guild_id
+            .and_then(|guild_id| blocklists.get(&guild_id).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Explains that the message only links things that can't be added.
+    /// `UNSUPPORTED_LINK_FEEDBACK=false` keeps the bot quiet about them.
+    async fn reply_unsupported(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        kinds: &[&str],
+    ) {
+        info!("Message only links unsupported {}", kinds.join(" and "));
+        if !env_or("UNSUPPORTED_LINK_FEEDBACK", true) {
+            return;
+        }
+        let mut content = kinds.join(" and ");
+        if let Some(first) = content.get_mut(..1) {
+            first.make_ascii_uppercase();
+        }
+        content.push_str(" aren't supported, only Spotify tracks can be added");
+        let mode = self.feedback_mode(msg).await;
+        feedback::respond(ctx, msg, mode, feedback::FAILED_REACTION, content)
+            .await
+    }
+
+    /// Looks SoundCloud and Bandcamp links up on Spotify and offers to add
+    /// what it finds. Only full feedback replies, since the offer needs one.
+    async fn suggest_spotify_matches(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        links: &[String],
+    ) {
+        if self.feedback_mode(msg).await != FeedbackMode::Full {
+            return;
+        }
+        let mut spotify_client = self.spotify_client.clone();
+        let mut matches = Vec::new();
+        for link in links.iter().take(MAX_EXTERNAL_LINKS) {
+            let query = match self.external_links.search_query(link) {
+                Ok(Some(query)) => query,
+                Ok(None) => {
+                    info!("No track metadata on {}", link);
+                    continue;
+                }
+                Err(why) => {
+                    error!("Could not read {}: {:?}", link, why);
+                    continue;
+                }
+            };
+            match spotify_client.search_track(&query) {
+                Ok(Some(track)) => matches.push(track),
+                Ok(None) => info!("No Spotify match for {}", query),
+                Err(why) => error!("Could not search for {}: {}", query, why),
+            }
+        }
+        if matches.is_empty() {
+            return;
+        }
+
+        let mut content = String::from("Found on Spotify:");
+        for track in &matches {
+            content.push_str(&format!(
+                "\n• **{}** — {}",
+                track.name,
+                track.artists.join(", ")
+            ));
+        }
+        let result = msg
+            .channel_id
+            .send_message(&ctx.http, |message| {
+                message.reference_message(msg).content(content).components(
+                    |components| {
+                        components.create_action_row(|row| {
+                            for track in &matches {
+                                let track_id = track
+                                    .uri
+                                    .rsplit(':')
+                                    .next()
+                                    .unwrap_or_default();
+                                let label: String =
+                                    format!("Add {}", track.name)
+                                        .chars()
+                                        .take(MAX_BUTTON_LABEL_CHARS)
+                                        .collect();
+                                row.create_button(|button| {
+                                    button
+                                        .style(ButtonStyle::Primary)
+                                        .label(label)
+                                        .custom_id(format!(
+                                            "{ADD_MATCH_ID}{track_id}"
+                                        ))
+                                });
+                            }
+                            row
+                        })
+                    },
+                )
+            })
+            .await;
+        if let Err(why) = result {
+            error!("Could not suggest Spotify matches: {:?}", why);
+        }
+    }
+
+    /// Enforces `MAX_LINKS_PER_MESSAGE` and the member's daily quota,
+    /// explaining any refusal.
+    fn check_submission_limits(
+        &self,
+        user_id: UserId,
+        track_ids: &[String],
+    ) -> Result<usize, String> {
+        let count = track_ids.iter().collect::<HashSet<_>>().len();
+        quota::check_link_count(count)?;
+        let exceeded = match self.submission_quota.take(user_id, count) {
+            Ok(()) => return Ok(count),
+            Err(exceeded) => exceeded,
+        };
+        let mut reason = exceeded.reason(count);
+        if let Some(retry_in) = exceeded.retry_in {
+            let retry_at = (SystemTime::now() + retry_in)
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            reason.push_str(&format!(", try again <t:{retry_at}:R>"));
+        }
+        Err(reason)
+    }
+
+    /// Refuses an add button pressed by a blocked or muted member, or one
+    /// over their quota, as their links would be. Otherwise takes the track
+    /// from their quota.
+    fn check_button_add(
+        &self,
+        user_id: UserId,
+        blocklist: &Blocklist,
+        track_id: &str,
+    ) -> Result<(), String> {
+        if blocklist.is_user_blocked(user_id) {
+            return Err("You can't add tracks in this server".to_string());
+        }
+        if self.moderation.is_muted(&user_id) {
+            return Err("Your links are being ignored for now".to_string());
+        }
+        self.check_submission_limits(user_id, &[track_id.to_string()])
+            .map(|_| ())
+    }
+
+    /// Gives a button's track back to the member's quota unless it was
+    /// added.
+    fn refund_unless_added(
+        &self,
+        user_id: UserId,
+        outcome: &Result<AddOutcome, SpotifyError>,
+    ) {
+        if !matches!(outcome, Ok(AddOutcome::Added(_))) {
+            self.submission_quota.refund(&user_id, 1);
+        }
+    }
+
+    /// Looks up the artists of the linked tracks and has `moderation` check
+    /// the member's recent links for spam. The lookups are cached, so adding
+    /// the tracks afterwards doesn't repeat them.
+    fn screen_for_spam(
+        &self,
+        msg: &Message,
+        track_ids: &[String],
+    ) -> Option<moderation::Flagged> {
+        let mut spotify_client = self.spotify_client.clone();
+        // Tracks that can't be looked up are reported when they are added
+        let links = track_ids
+            .iter()
+            .map(|track_id| moderation::Link {
+                track_id: track_id.clone(),
+                artists: spotify_client
+                    .get_track(track_id)
+                    .map(|track| track.artists)
+                    .unwrap_or_default(),
+            })
+            .collect();
+        self.moderation.screen(msg.author.id, links)
+    }
+
+    /// Tells the member their links were not added, as loudly as the
+    /// server's feedback mode allows.
+    async fn refuse_links(&self, ctx: &Context, msg: &Message, reason: &str) {
+        let mode = self.feedback_mode(msg).await;
+        feedback::respond(ctx, msg, mode, feedback::FAILED_REACTION, reason)
+            .await
+    }
+
+    async fn feedback_mode(&self, msg: &Message) -> FeedbackMode {
+        let modes = self.feedback_modes.lock().await;
+        msg.guild_id
+            .and_then(|guild_id| modes.get(&guild_id).copied())
+            .unwrap_or(self.default_feedback_mode)
+    }
+
+    /// Follows the artists of a just added track on Spotify once they are
+    /// credited on `AUTO_FOLLOW_ARTIST_THRESHOLD` tracks (0, the default,
+    /// turns this off), and says so in the channel.
+    async fn follow_shared_artists(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        track: &TrackInfo,
+    ) {
+        let threshold = env_or("AUTO_FOLLOW_ARTIST_THRESHOLD", 0usize);
+        if threshold == 0 {
+            return;
+        }
+        for (artist_id, artist_name) in
+            track.artist_ids.iter().zip(&track.artists)
+        {
+            // Only the add that crosses the threshold follows, so an artist
+            // unfollowed by hand isn't followed again on every track
+            if self.playlist_cache.artist_track_count(artist_id) != threshold {
+                continue;
+            }
+            if let Err(why) = self.spotify_client.follow_artist(artist_id) {
+                error!("Could not follow artist {}: {}", artist_name, why);
+                continue;
+            }
+            let content = format!(
+                "Followed **{artist_name}** on Spotify, now on {threshold} \
+                 tracks in the playlist"
+            );
+            if let Err(why) = channel_id.say(&ctx.http, content).await {
+                error!("Could not announce followed artist: {:?}", why);
+            }
+        }
+    }
+
+    /// Adds the track linked in a message. Returns 1 if it was added, else
+    /// 0.
+    async fn add_track(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        track_id: &str,
+    ) -> usize {
+        let mode = self.feedback_mode(msg).await;
+        let blocklist = self.blocklist(msg.guild_id).await;
+        // Spotify round trips can take a few seconds, show that we're on it
+        let typing = msg.channel_id.start_typing(&ctx.http);
+        let mut spotify_client = self.spotify_client.clone();
+        let outcome = self.playlist_cache.add_unless_present(
+            &mut spotify_client,
+            &blocklist,
+            track_id,
+        );
+        let (track_count, colour, preview, universal_url) = match &outcome {
+            Ok(AddOutcome::Added(track)) => {
+                // The track is in, so a failed count only skips the milestone
+                let track_count = spotify_client
+                    .get_playlist_snapshot(spotify_client::PLAYLIST_ID)
+                    .map(|snapshot| snapshot.track_count)
+                    .map_err(|why| {
+                        error!("Could not count playlist tracks: {:?}", why)
+                    })
+                    .ok();
+                // Only full replies show the cover and preview
+                let colour = track
+                    .album_image_url
+                    .as_deref()
+                    .filter(|_| mode == FeedbackMode::Full)
+                    .and_then(|image_url| self.album_colours.get(image_url));
+                let preview = track
+                    .preview_url
+                    .as_deref()
+                    .filter(|_| {
+                        mode == FeedbackMode::Full
+                            && env_or("ATTACH_PREVIEWS", false)
+                    })
+                    .and_then(|preview_url| {
+                        spotify_client
+                            .download_preview(preview_url, MAX_PREVIEW_BYTES)
+                            .map_err(|why| {
+                                error!("Could not download preview: {:?}", why)
+                            })
+                            .ok()
+                    });
+                let universal_url = self
+                    .songlink
+                    .as_ref()
+                    .filter(|_| mode == FeedbackMode::Full)
+                    .and_then(|songlink| {
+                        songlink
+                            .lookup(&track.url())
+                            .map_err(|why| {
+                                error!("Could not look up song.link: {:?}", why)
+                            })
+                            .ok()
+                    })
+                    .map(|entry| entry.page_url);
+                (track_count, colour, preview, universal_url)
+            }
+            _ => (None, None, None, None),
+        };
+        if let Ok(typing) = typing {
+            let _ = typing.stop();
+        }
+        let added = usize::from(matches!(outcome, Ok(AddOutcome::Added(_))));
+        match outcome {
+            Ok(AddOutcome::Added(track)) => {
+                match mode {
+                    FeedbackMode::Full => {
+                        feedback::reply_track_added(
+                            ctx,
+                            msg,
+                            &track,
+                            colour,
+                            preview,
+                            universal_url,
+                        )
+                        .await
+                    }
+                    FeedbackMode::Reactions => {
+                        feedback::react(ctx, msg, feedback::ADDED_REACTION)
+                            .await
+                    }
+                    FeedbackMode::Silent => {}
+                }
+                if let Some(track_count) = track_count {
+                    announce_milestone(ctx, msg, &track, track_count).await;
+                }
+                self.follow_shared_artists(ctx, msg.channel_id, &track)
+                    .await;
+            }
+            Ok(AddOutcome::AlreadyInPlaylist(track)) => {
+                let content = format!(
+                    "**{}** — {} is already in the playlist",
+                    track.name,
+                    track.artists.join(", ")
+                );
+                let reaction = feedback::DUPLICATE_REACTION;
+                feedback::respond(ctx, msg, mode, reaction, content).await
+            }
+            Ok(AddOutcome::Unplayable(track)) => {
+                let content = format!(
+                    "**{}** — {} can't be played in the bot's Spotify \
+                     market, so it wasn't added",
+                    track.name,
+                    track.artists.join(", ")
+                );
+                let reaction = feedback::FAILED_REACTION;
+                feedback::respond(ctx, msg, mode, reaction, content).await
+            }
+            Ok(AddOutcome::Blocked(track, artist)) => {
+                let reason = format!("{artist} is blocked in this server");
+                quarantine(ctx, msg, &[track_id], &reason).await;
+                let content =
+                    format!("**{}** wasn't added, {}", track.name, reason);
+                let reaction = feedback::FAILED_REACTION;
+                feedback::respond(ctx, msg, mode, reaction, content).await
+            }
+            Err(why) => {
+                error!("Could not add track {}: {}", track_id, why);
+                let content = describe_spotify_error(
+                    &why,
+                    "Could not find that track on Spotify",
+                    "Could not add that track to the playlist",
+                );
+                let reaction = feedback::FAILED_REACTION;
+                feedback::respond(ctx, msg, mode, reaction, content).await
+            }
+        }
+        added
+    }
+
+    /// Adds every track linked in a message and answers with one summary
+    /// instead of a reply per link. Returns how many were added.
+    async fn add_tracks(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        track_ids: &[String],
+    ) -> usize {
+        let mode = self.feedback_mode(msg).await;
+        let blocklist = self.blocklist(msg.guild_id).await;
+        let typing = msg.channel_id.start_typing(&ctx.http);
+        let mut progress = match mode {
+            FeedbackMode::Full => Some(
+                Progress::start(
+                    &ctx.http,
+                    msg.channel_id,
+                    "Adding tracks",
+                    track_ids.len(),
+                )
+                .await,
+            ),
+            _ => None,
+        };
+        let mut spotify_client = self.spotify_client.clone();
+        // Checks every link for duplicates in one pass, instead of once per
+        // track
+        let uris: Vec<String> = track_ids
+            .iter()
+            .map(|track_id| format!("spotify:track:{track_id}"))
+            .collect();
+        let uris: Vec<&str> = uris.iter().map(String::as_str).collect();
+        let present =
+            self.playlist_cache.contains_all(&mut spotify_client, &uris);
+        let mut seen = HashSet::new();
+        let mut lines = Vec::new();
+        let mut added = Vec::new();
+        let (mut duplicates, mut unplayable, mut blocked, mut failed) =
+            (0, 0, 0, 0);
+        for (track_id, present) in track_ids.iter().zip(present) {
+            if let Some(progress) = &mut progress {
+                progress.advance().await;
+            }
+            if !seen.insert(track_id) {
+                duplicates += 1;
+                lines.push(format!("🔁 `{track_id}` was linked twice"));
+                continue;
+            }
+            // The market may have relinked the track to another URI
+            let outcome =
+                spotify_client.get_track(track_id).and_then(|track| {
+                    let present =
+                        present || self.playlist_cache.contains(&track.uri);
+                    self.playlist_cache.add_checked(
+                        &mut spotify_client,
+                        &blocklist,
+                        track,
+                        present,
+                    )
+                });
+            match outcome {
+                Ok(AddOutcome::AlreadyInPlaylist(track)) => {
+                    duplicates += 1;
+                    lines.push(format!(
+                        "🔁 **{}** — {} is already in the playlist",
+                        track.name,
+                        track.artists.join(", ")
+                    ));
+                }
+                Ok(AddOutcome::Unplayable(track)) => {
+                    unplayable += 1;
+                    lines.push(format!(
+                        "🚫 **{}** — {} can't be played in the bot's market",
+                        track.name,
+                        track.artists.join(", ")
+                    ));
+                }
+                Ok(AddOutcome::Blocked(track, artist)) => {
+                    blocked += 1;
+                    let reason = format!("{artist} is blocked in this server");
+                    quarantine(ctx, msg, &[track_id], &reason).await;
+                    lines.push(format!("⛔ **{}** — {}", track.name, reason));
+                }
+                Ok(AddOutcome::Added(track)) => {
+                    lines.push(format!(
+                        "✅ **{}** — {}",
+                        track.name,
+                        track.artists.join(", ")
+                    ));
+                    self.follow_shared_artists(ctx, msg.channel_id, &track)
+                        .await;
+                    added.push(track);
+                }
+                Err(why) => {
+                    error!("Could not add track {}: {}", track_id, why);
+                    failed += 1;
+                    let reason = describe_spotify_error(
+                        &why,
+                        "Could not find that track on Spotify",
+                        "Could not add that track to the playlist",
+                    );
+                    lines.push(format!("❌ `{track_id}`: {reason}"));
+                }
+            }
+        }
+        let track_count = if added.is_empty() {
+            None
+        } else {
+            spotify_client
+                .get_playlist_snapshot(spotify_client::PLAYLIST_ID)
+                .map(|snapshot| snapshot.track_count)
+                .map_err(|why| {
+                    error!("Could not count playlist tracks: {:?}", why)
+                })
+                .ok()
+        };
+        if let Ok(typing) = typing {
+            let _ = typing.stop();
+        }
+
+        let mut summary = format!("Added {}", added.len());
+        if duplicates > 0 {
+            let plural = if duplicates == 1 { "" } else { "s" };
+            summary
+                .push_str(&format!(", skipped {duplicates} duplicate{plural}"));
+        }
+        if unplayable > 0 {
+            summary.push_str(&format!(", {unplayable} unplayable"));
+        }
+        if blocked > 0 {
+            summary.push_str(&format!(", {blocked} blocked"));
+        }
+        if failed > 0 {
+            summary.push_str(&format!(", {failed} failed"));
+        }
+        if lines.len() > MAX_LISTED_TRACKS {
+            let hidden = lines.len() - MAX_LISTED_TRACKS;
+            lines.truncate(MAX_LISTED_TRACKS);
+            lines.push(format!("…and {hidden} more"));
+        }
+        let reactions = [
+            (!added.is_empty(), feedback::ADDED_REACTION),
+            (duplicates > 0, feedback::DUPLICATE_REACTION),
+            (failed + unplayable + blocked > 0, feedback::FAILED_REACTION),
+        ];
+        feedback::respond_summary(
+            ctx,
+            msg,
+            mode,
+            &reactions,
+            summary,
+            lines.join("\n"),
+        )
+        .await;
+        if let Some(progress) = progress {
+            progress.finish().await;
+        }
+
+        // The batch took the last `added.len()` positions of the playlist
+        if let Some(track_count) = track_count {
+            let first_position = (track_count + 1).saturating_sub(added.len());
+            for (position, track) in (first_position..).zip(&added) {
+                announce_milestone(ctx, msg, track, position).await;
+            }
+        }
+        added.len()
+    }
+
+    async fn queue_track(
+        &self,
+        options: &[CommandDataOption],
+    ) -> CommandResult {
+        let track_id = match option_value(options, "url")
+            .and_then(Value::as_str)
+            .and_then(|input| parse_spotify_id(input, "track"))
+        {
+            Some(track_id) => track_id,
+            None => {
+                return Err(
+                    "That doesn't look like a Spotify track link".to_string()
+                )
+            }
+        };
+
+        let mut spotify_client = self.spotify_client.clone();
+        let track = match spotify_client.get_track(&track_id) {
+            Ok(track) => track,
+            Err(why) => {
+                error!("Could not fetch track {}: {:?}", track_id, why);
+                return Err(describe_spotify_error(
+                    &why,
+                    "Could not find that track on Spotify",
+                    "Could not fetch that track from Spotify",
+                ));
+            }
+        };
+        match spotify_client.add_to_queue(&track.uri) {
+            Ok(()) => Ok(format!(
+                "Queued **{}** — {}",
+                track.name,
+                track.artists.join(", ")
+            )),
+            Err(why) => {
+                error!("Could not queue track {}: {:?}", track.uri, why);
+                // Spotify answers 404 when no device is playing
+                Err(describe_spotify_error(
+                    &why,
+                    "Could not queue the track, is Spotify playing on a device?",
+                    "Could not queue the track",
+                ))
+            }
+        }
+    }
+
+    async fn run_quiz_command(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+    ) -> CommandResult {
+        match command
+            .data
+            .options
+            .first()
+            .map(|option| option.name.as_str())
+        {
+            Some("start") => self.start_quiz(ctx, command.channel_id).await,
+            Some("scores") => Ok(self.quiz_scores().await),
+            _ => Err("Missing quiz subcommand".to_string()),
+        }
+    }
+
+    async fn start_quiz(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+    ) -> CommandResult {
+        let mut spotify_client = self.spotify_client.clone();
+        let tracks = match spotify_client
+            .get_playlist_tracks(spotify_client::PLAYLIST_ID)
+        {
+            Ok(tracks) => tracks,
+            Err(why) => {
+                error!("Could not fetch the playlist for a quiz: {:?}", why);
+                return Err(
+                    "Could not fetch the collaborative playlist".to_string()
+                );
+            }
+        };
+        let track = match tracks
+            .into_iter()
+            .filter(|track| track.preview_url.is_some())
+            .choose(&mut rand::thread_rng())
+        {
+            Some(track) => track,
+            None => {
+                return Err("No track in the playlist has a preview to play"
+                    .to_string())
+            }
+        };
+        let preview_url = track.preview_url.clone().unwrap_or_default();
+
+        let round_id = match self.quiz.lock().await.start(channel_id, track) {
+            Some(round_id) => round_id,
+            None => {
+                return Err(
+                    "A quiz is already running in this channel".to_string()
+                )
+            }
+        };
+        let duration_secs =
+            env_or("QUIZ_DURATION_SECS", DEFAULT_QUIZ_DURATION_SECS);
+        tokio::spawn(expire_quiz_round(
+            ctx.clone(),
+            self.quiz.clone(),
+            channel_id,
+            round_id,
+            Duration::from_secs(duration_secs),
+        ));
+        Ok(format!(
+            "🎵 **Guess the track!** The first to name its title or an \
+             artist within {duration_secs} seconds wins a point.\n{preview_url}"
+        ))
+    }
+
+    async fn quiz_scores(&self) -> String {
+        let leaderboard = self.quiz.lock().await.leaderboard();
+        if leaderboard.is_empty() {
+            return "Nobody has scored yet".to_string();
+        }
+        leaderboard
+            .iter()
+            .take(MAX_LEADERBOARD_ENTRIES)
+            .enumerate()
+            .map(|(rank, (user_id, score))| {
+                format!("{}. <@{}> — {} points", rank + 1, user_id, score)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Returns whether the message was a winning quiz guess.
+    async fn check_quiz_guess(&self, ctx: &Context, msg: &Message) -> bool {
+        let result = self.quiz.lock().await.guess(
+            msg.channel_id,
+            msg.author.id,
+            &msg.content,
+        );
+        let (track, score) = match result {
+            Some(result) => result,
+            None => return false,
+        };
+        let content = format!(
+            "🎉 Correct! It was **{}** — {}. You now have {} points.",
+            track.name,
+            track.artists.join(", "),
+            score
+        );
+        if let Err(why) = msg.reply(&ctx.http, content).await {
+            error!("Could not announce the quiz winner: {:?}", why);
+        }
+        true
+    }
+
+    /// Summarises Spotify request statistics per endpoint, so a slow or
+    /// failing endpoint stands out.
+    fn spotify_status(&self) -> String {
+        let endpoints = self.spotify_client.metrics().endpoints();
+        if endpoints.is_empty() {
+            return "No Spotify requests yet".to_string();
+        }
+        endpoints
+            .iter()
+            .map(|(endpoint, stats)| format_stats(endpoint, stats, "requests"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// How often each command ran and failed since startup, busiest first,
+    /// followed by the errors members saw most.
+    fn command_usage(&self) -> String {
+        let mut commands: Vec<(String, EndpointStats)> =
+            self.command_metrics.runs.endpoints().into_iter().collect();
+        if commands.is_empty() {
+            return "No commands run since startup".to_string();
+        }
+        commands.sort_by_key(|(_, stats)| Reverse(stats.requests));
+        let mut lines: Vec<String> = commands
+            .iter()
+            .map(|(command, stats)| format_stats(command, stats, "runs"))
+            .collect();
+        let errors = self.command_metrics.top_errors(MAX_LISTED_ERRORS);
+        if !errors.is_empty() {
+            lines.push("\nMost common errors:".to_string());
+            lines.extend(
+                errors
+                    .iter()
+                    .map(|(error, count)| format!("{count}× {error}")),
+            );
+        }
+        lines.join("\n")
+    }
+
+    /// Lists the most common genres of the collaborative playlist's tracks.
+    async fn genre_breakdown(&self) -> CommandResult {
+        let mut spotify_client = self.spotify_client.clone();
+        let tracks = match spotify_client
+            .get_playlist_tracks(spotify_client::PLAYLIST_ID)
+        {
+            Ok(tracks) => tracks,
+            Err(why) => {
+                error!("Could not fetch the playlist: {:?}", why);
+                return Err(
+                    "Could not read the collaborative playlist".to_string()
+                );
+            }
+        };
+
+        let artist_ids: Vec<String> = tracks
+            .iter()
+            .flat_map(|track| track.artist_ids.iter().cloned())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let artist_genres = match spotify_client.get_artist_genres(&artist_ids)
+        {
+            Ok(artist_genres) => artist_genres,
+            Err(why) => {
+                error!("Could not fetch artist genres: {}", why);
+                return Err(
+                    "Could not look up the playlist's genres".to_string()
+                );
+            }
+        };
+
+        let breakdown =
+            playlist_manager::genre_breakdown(&tracks, &artist_genres);
+        if breakdown.is_empty() {
+            return Ok(
+                "Spotify has no genres for the playlist's artists".to_string()
+            );
+        }
+        let mut content = format!("Genres of {} tracks:", tracks.len());
+        for (genre, share) in breakdown.iter().take(MAX_LISTED_GENRES) {
+            content.push_str(&format!(
+                "\n**{}** — {:.1}%",
+                genre,
+                share * 100.0
+            ));
+        }
+        Ok(content)
+    }
+
+    async fn run_command(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+    ) {
+        let started = Instant::now();
+        let label = command_label(command);
+        // Acknowledge straight away, since commands that scan a playlist
+        // can take longer than the few seconds Discord waits for a reply
+        if let Err(why) = command
+            .create_interaction_response(&ctx.http, |response| {
+                response.kind(
+                    InteractionResponseType::DeferredChannelMessageWithSource,
+                )
+            })
+            .await
+        {
+            error!("Cannot respond to slash command: {:?}", why);
+            return;
+        }
+
+        let requirement = self
+            .command_permissions(command.guild_id)
+            .await
+            .requirement(&command.data.name);
+        if !requirement.allows(command.member.as_ref()) {
+            let content = format!(
+                "Only {} can use `/{}`",
+                requirement.describe(),
+                command.data.name
+            );
+            let result = Err(content.clone());
+            self.command_metrics
+                .record(&label, started.elapsed(), &result);
+            reply_privately(ctx, command, content).await;
+            return;
+        }
+
+        let is_health = command.data.name == "admin"
+            && command
+                .data
+                .options
+                .first()
+                .is_some_and(|subcommand| subcommand.name == "health");
+        let mut fixes = Vec::new();
+        let mut pin_reply = false;
+        let result = match command.data.name.as_str() {
+            // The health report is the only reply that comes with buttons
+            "admin" if is_health => {
+                let (content, health_fixes) = self.playlist_health().await;
+                fixes = health_fixes;
+                Ok(content)
+            }
+            "admin" => self.run_admin_command(command).await,
+            "discover" => {
+                let result =
+                    self.discover_by_tempo(&command.data.options).await;
+                pin_reply = result.is_ok();
+                result
+            }
+            "event" => self.create_event(ctx, command).await,
+            "session" => self.run_session_command(command).await,
+            "genres" => self.genre_breakdown().await,
+            "queue" => self.queue_track(&command.data.options).await,
+            "quiz" => self.run_quiz_command(ctx, command).await,
+            "status" => Ok(self.spotify_status()),
+            _ => Err(format!("Unknown command `{}`", command.data.name)),
+        };
+        self.command_metrics
+            .record(&label, started.elapsed(), &result);
+        let content = match result {
+            Ok(content) => content,
+            Err(why) => {
+                reply_privately(ctx, command, why).await;
+                return;
+            }
+        };
+        // Long replies, such as tracklists, continue in follow-up messages
+        let mut chunks = split_message(&content, MAX_MESSAGE_CHARS).into_iter();
+        let first = chunks.next().unwrap_or_default();
+        let reply = match command
+            .edit_original_interaction_response(&ctx.http, |response| {
+                // Leaderboards mention players, which should not ping them
+                response
+                    .content(first)
+                    .allowed_mentions(|mentions| mentions.empty_parse());
+                if !fixes.is_empty() {
+                    response.components(|components| {
+                        components.create_action_row(|row| {
+                            for (label, custom_id) in &fixes {
+                                row.create_button(|button| {
+                                    button
+                                        .style(ButtonStyle::Secondary)
+                                        .label(label)
+                                        .custom_id(custom_id)
+                                });
+                            }
+                            row
+                        })
+                    });
+                }
+                response
+            })
+            .await
+        {
+            Ok(reply) => reply,
+            Err(why) => {
+                error!("Cannot respond to slash command: {:?}", why);
+                return;
+            }
+        };
+        if pin_reply {
+            self.pin_discovery(ctx, &reply).await;
+        }
+        for chunk in chunks {
+            if let Err(why) = command
+                .create_followup_message(&ctx.http, |message| {
+                    message
+                        .content(chunk)
+                        .allowed_mentions(|mentions| mentions.empty_parse())
+                })
+                .await
+            {
+                error!("Cannot continue slash command reply: {:?}", why);
+                return;
+            }
+        }
+    }
+
+    /// Pins the reply to the latest `/discover` in its channel and unpins the
+    /// one before it, so the channel's pins lead to the current playlist.
+    async fn pin_discovery(&self, ctx: &Context, reply: &Message) {
+        if let Err(why) = reply.pin(&ctx.http).await {
+            error!("Could not pin discovery reply: {:?}", why);
+            return;
+        }
+        let previous = self
+            .discovery_pins
+            .lock()
+            .await
+            .insert(reply.channel_id, reply.id);
+        if let Some(previous) = previous {
+            if let Err(why) = reply.channel_id.unpin(&ctx.http, previous).await
+            {
+                error!("Could not unpin previous discovery reply: {:?}", why);
+            }
+        }
+    }
+
+    /// Handles the buttons on the bot's own messages.
+    async fn run_component(
+        &self,
+        ctx: &Context,
+        component: &MessageComponentInteraction,
+    ) {
+        let custom_id = component.data.custom_id.as_str();
+        if ![ADD_RELEASE_ID, ADD_MATCH_ID, OVERRIDE_ADD_ID, HEALTH_FIX_ID]
+            .iter()
+            .any(|prefix| custom_id.starts_with(prefix))
+        {
+            return;
+        }
+        // Only the member who pressed the button sees the outcome
+        if let Err(why) = component
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                    .interaction_response_data(|data| data.ephemeral(true))
+            })
+            .await
+        {
+            error!("Cannot respond to button: {:?}", why);
+            return;
+        }
+
+        let content = if let Some(album_id) =
+            custom_id.strip_prefix(ADD_RELEASE_ID)
+        {
+            self.add_release(component, album_id).await
+        } else if let Some(track_id) = custom_id.strip_prefix(ADD_MATCH_ID) {
+            self.add_match(component, track_id).await
+        } else if let Some(track_id) = custom_id.strip_prefix(OVERRIDE_ADD_ID) {
+            self.override_rejection(component, track_id).await
+        } else if let Some(fix) = custom_id.strip_prefix(HEALTH_FIX_ID) {
+            // Fixes act on behalf of `/admin health`
+            let requirement = self
+                .command_permissions(component.guild_id)
+                .await
+                .requirement("admin");
+            if !requirement.allows(component.member.as_ref()) {
+                format!("Only {} can fix the playlist", requirement.describe())
+            } else if fix == "duplicates" {
+                self.dedupe(true).await.unwrap_or_else(|why| why)
+            } else if fix == "unplayable" {
+                self.remove_unplayable().await.unwrap_or_else(|why| why)
+            } else {
+                // The response is already deferred, so it must be answered
+                warn!("Unknown playlist health fix {:?}", fix);
+                "Unknown fix".to_string()
+            }
+        } else {
+            return;
+        };
+        if let Err(why) = component
+            .edit_original_interaction_response(&ctx.http, |response| {
+                response.content(content)
+            })
+            .await
+        {
+            error!("Cannot respond to button: {:?}", why);
+        }
+    }
+
+    /// Adds the lead track of an announced release.
+    async fn add_release(
+        &self,
+        component: &MessageComponentInteraction,
+        album_id: &str,
+    ) -> String {
+        let blocklist = self.blocklist(component.guild_id).await;
+        let user_id = component.user.id;
+        let mut spotify_client = self.spotify_client.clone();
+        let outcome = match spotify_client.get_lead_track_id(album_id) {
+            Ok(track_id) => {
+                if let Err(reason) =
+                    self.check_button_add(user_id, &blocklist, &track_id)
+                {
+                    return reason;
+                }
+                let outcome = self.playlist_cache.add_unless_present(
+                    &mut spotify_client,
+                    &blocklist,
+                    &track_id,
+                );
+                self.refund_unless_added(user_id, &outcome);
+                outcome
+            }
+            Err(why) => Err(why),
+        };
+        match outcome {
+            Ok(outcome) => describe_outcome(&outcome),
+            Err(why) => {
+                error!("Could not add release {}: {}", album_id, why);
+                describe_spotify_error(
+                    &why,
+                    "Could not find that release on Spotify",
+                    "Could not add that release to the playlist",
+                )
+            }
+        }
+    }
+
+    /// Adds the Spotify match suggested for a SoundCloud or Bandcamp link.
+    async fn add_match(
+        &self,
+        component: &MessageComponentInteraction,
+        track_id: &str,
+    ) -> String {
+        let blocklist = self.blocklist(component.guild_id).await;
+        let user_id = component.user.id;
+        if let Err(reason) =
+            self.check_button_add(user_id, &blocklist, track_id)
+        {
+            return reason;
+        }
+        let mut spotify_client = self.spotify_client.clone();
+        let outcome = self.playlist_cache.add_unless_present(
+            &mut spotify_client,
+            &blocklist,
+            track_id,
+        );
+        self.refund_unless_added(user_id, &outcome);
+        match outcome {
+            Ok(outcome) => describe_outcome(&outcome),
+            Err(why) => {
+                error!("Could not add track {}: {}", track_id, why);
+                describe_spotify_error(
+                    &why,
+                    "Could not find that track on Spotify",
+                    "Could not add that track to the playlist",
+                )
+            }
         }
     }
-}
 
-// impl Handler {
-//     fn new() -> &'static mut Handler {
-//         let mut  spotify_client = spotify_client::SpotifyClient::new();
-//         &mut Handler {
-//             spotify_client,
-//         }
-//     }
-// }
+    /// Adds a quarantined track regardless of the server's blocklist. Only
+    /// members who may use `/admin` can do this.
+    async fn override_rejection(
+        &self,
+        component: &MessageComponentInteraction,
+        track_id: &str,
+    ) -> String {
+        let requirement = self
+            .command_permissions(component.guild_id)
+            .await
+            .requirement("admin");
+        if !requirement.allows(component.member.as_ref()) {
+            return format!(
+                "Only {} can override a rejection",
+                requirement.describe()
+            );
+        }
+        let mut spotify_client = self.spotify_client.clone();
+        match self.playlist_cache.add_unless_present(
+            &mut spotify_client,
+            &Blocklist::default(),
+            track_id,
+        ) {
+            Ok(outcome) => {
+                if let AddOutcome::Added(track) = &outcome {
+                    info!(
+                        "{} overrode the rejection of {}",
+                        component.user.tag(),
+                        track.uri
+                    );
+                }
+                describe_outcome(&outcome)
+            }
+            Err(why) => {
+                error!("Could not add track {}: {}", track_id, why);
+                describe_spotify_error(
+                    &why,
+                    "Could not find that track on Spotify",
+                    "Could not add that track to the playlist",
+                )
+            }
+        }
+    }
+}
 
 #[async_trait]
 impl EventHandler for Handler {
     async fn message(&self, ctx: Context, msg: Message) {
         if !msg.author.bot {
-            // Try to see if a URL is in the message
-            let url = Url::parse(&msg.content);
-            match url {
-                Ok(url) => {
-                    let id = url.path().split("/").nth(2);
-                    let track_uri = self
-                        .spotify_client
-                        .clone()
-                        .get_track_uri(id.unwrap())
-                        .to_string();
-                    self.spotify_client.add_to_playlist(&track_uri);
+            if self.check_quiz_guess(&ctx, &msg).await {
+                return;
+            }
+            let mut track_ids = find_track_ids(&msg.content);
+            if let Some(songlink) = &self.songlink {
+                track_ids.extend(resolve_songlink_urls(songlink, &msg.content));
+            }
+            if !track_ids.is_empty()
+                && self
+                    .blocklist(msg.guild_id)
+                    .await
+                    .is_user_blocked(msg.author.id)
+            {
+                info!("Ignoring links from blocked user {}", msg.author.tag());
+                let reason =
+                    format!("{} is blocked in this server", msg.author);
+                let mut seen = HashSet::new();
+                let unique: Vec<&str> = track_ids
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|track_id| seen.insert(*track_id))
+                    .collect();
+                quarantine(&ctx, &msg, &unique, &reason).await;
+                self.refuse_links(
+                    &ctx,
+                    &msg,
+                    "You can't add tracks in this server",
+                )
+                .await;
+                return;
+            }
+            if !track_ids.is_empty() && self.moderation.is_muted(&msg.author.id)
+            {
+                info!("Ignoring links from muted member {}", msg.author.tag());
+                return;
+            }
+            // Taken up front so a member over their quota is told straight
+            // away, and refunded below for every track that isn't added
+            let mut taken = 0;
+            if !track_ids.is_empty() {
+                match self.check_submission_limits(msg.author.id, &track_ids) {
+                    Ok(count) => taken = count,
+                    Err(reason) => {
+                        info!(
+                            "Refusing links from {}: {}",
+                            msg.author.tag(),
+                            reason
+                        );
+                        self.refuse_links(&ctx, &msg, &reason).await;
+                        return;
+                    }
+                }
+                if let Some(flagged) = self.screen_for_spam(&msg, &track_ids) {
+                    warn!(
+                        "Muting links from {}: {}",
+                        msg.author.tag(),
+                        flagged.reason
+                    );
+                    self.submission_quota.refund(&msg.author.id, taken);
+                    let posted_by = msg.author.mention().to_string();
+                    alert_moderators(
+                        &ctx.http,
+                        posted_by,
+                        Some(msg.link()),
+                        &flagged,
+                    )
+                    .await;
+                    return;
                 }
-                Err(_) => info!("Message does not contain a URL"),
             }
+            let added = if track_ids.is_empty() {
+                0
+            } else if self.events.lock().await.contains_key(&msg.channel_id) {
+                self.add_to_event(&ctx, &msg, &track_ids).await
+            } else if self.sessions.lock().await.contains_key(&msg.channel_id) {
+                self.queue_for_session(&ctx, &msg, &track_ids).await
+            } else if let [track_id] = track_ids.as_slice() {
+                self.add_track(&ctx, &msg, track_id).await
+            } else {
+                self.add_tracks(&ctx, &msg, &track_ids).await
+            };
+            self.submission_quota
+                .refund(&msg.author.id, taken.saturating_sub(added));
+            if track_ids.is_empty() {
+                let unsupported = find_unsupported_links(&msg.content);
+                if !unsupported.is_empty() {
+                    self.reply_unsupported(&ctx, &msg, &unsupported).await;
+                    return;
+                }
+                let links = external_links::find_external_links(&msg.content);
+                if !links.is_empty() {
+                    self.suggest_spotify_matches(&ctx, &msg, &links).await;
+                    return;
+                }
+                info!("Message does not contain a Spotify track link");
+            }
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::ApplicationCommand(command) => {
+                self.run_command(&ctx, &command).await
+            }
+            Interaction::MessageComponent(component) => {
+                self.run_component(&ctx, &component).await
+            }
+            _ => {}
         }
     }
 
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         info!("{} is connected!", ready.user.name);
+        check_message_content_intent(&ready);
+        systemd::notify_ready();
+
+        if let Err(why) = Command::set_global_application_commands(
+            &ctx.http,
+            register_commands,
+        )
+        .await
+        {
+            error!("Could not register slash commands: {:?}", why);
+        }
+
+        // Ready fires again on reconnects, only start background tasks once
+        if !self.background_tasks_started.swap(true, Ordering::SeqCst) {
+            tokio::spawn(poll_followed_playlists(
+                ctx.clone(),
+                self.spotify_client.clone(),
+                self.followed_playlists.clone(),
+                self.playlist_cache.clone(),
+                self.blocklists.clone(),
+            ));
+            let channel_id = env_or("NEW_RELEASES_CHANNEL_ID", 0u64);
+            if channel_id != 0 {
+                tokio::spawn(watch_new_releases(
+                    ctx.clone(),
+                    self.spotify_client.clone(),
+                    ChannelId(channel_id),
+                ));
+            }
+            for source in self.chat_sources.lock().await.drain(..) {
+                ChatBridge::start(
+                    source,
+                    Some(ctx.http.clone()),
+                    self.spotify_client.clone(),
+                    self.playlist_cache.clone(),
+                );
+            }
+            let channel_id = env_or("SONG_OF_THE_DAY_CHANNEL_ID", 0u64);
+            if channel_id != 0 {
+                tokio::spawn(post_songs_of_the_day(
+                    ctx,
+                    self.spotify_client.clone(),
+                    self.album_colours.clone(),
+                    ChannelId(channel_id),
+                ));
+            }
+        }
+    }
+}
+
+fn register_commands(
+    commands: &mut CreateApplicationCommands,
+) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("queue")
+            .description("Add a track to the live Spotify playback queue")
+            .create_option(|option| {
+                option
+                    .name("url")
+                    .description("Spotify track URL")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+    });
+    commands.create_application_command(|command| {
+        command
+            .name("quiz")
+            .description("Guess the track from its preview")
+            .dm_permission(false)
+            .create_option(|option| {
+                option
+                    .name("start")
+                    .description(
+                        "Play a preview of a random collaborative playlist track",
+                    )
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("scores")
+                    .description("Show the quiz leaderboard")
+                    .kind(CommandOptionType::SubCommand)
+            })
+    });
+    commands.create_application_command(|command| {
+        command
+            .name("discover")
+            .description(
+                "Fill the secondary playlist with tracks in a tempo range",
+            )
+            .dm_permission(false)
+            .create_option(|option| {
+                option
+                    .name("bpm")
+                    .description(
+                        "Tempo range in beats per minute, e.g. 120-140",
+                    )
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+            .create_option(|option| {
+                option
+                    .name("minutes")
+                    .description("Roughly how long the playlist should last")
+                    .kind(CommandOptionType::Integer)
+                    .min_int_value(DISCOVER_DURATION_TOLERANCE_MINUTES)
+                    .max_int_value(MAX_DISCOVER_MINUTES)
+            })
+            .create_option(|option| {
+                option
+                    .name("source")
+                    .description("Where candidate tracks come from")
+                    .kind(CommandOptionType::String)
+                    .add_string_choice("The collaborative playlist", "playlist")
+                    .add_string_choice(
+                        "\"This Is\" playlists of the server's top artists",
+                        "editorial",
+                    )
+            })
+    });
+    commands.create_application_command(|command| {
+        command
+            .name("event")
+            .description("Collect a channel's links in a temporary playlist")
+            .dm_permission(false)
+            .create_option(|option| {
+                option
+                    .name("create")
+                    .description(
+                        "Create an event playlist that expires after a while",
+                    )
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("name")
+                            .description("Name of the event and its playlist")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("duration")
+                            .description("How many days the event lasts")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(1)
+                            .max_int_value(MAX_EVENT_DAYS)
+                            .required(true)
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("channel")
+                            .description(
+                                "Channel or thread to collect links from, \
+                                 this one if not given",
+                            )
+                            .kind(CommandOptionType::Channel)
+                    })
+            })
+    });
+    commands.create_application_command(|command| {
+        command
+            .name("session")
+            .description("Queue this channel's links live on the bot's player")
+            .dm_permission(false)
+            .create_option(|option| {
+                option
+                    .name("start")
+                    .description("Start queueing links posted here")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("name")
+                            .description("Name of the session and its recap")
+                            .kind(CommandOptionType::String)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("end")
+                    .description("End the session and post a recap playlist")
+                    .kind(CommandOptionType::SubCommand)
+            })
+    });
+    commands.create_application_command(|command| {
+        command
+            .name("genres")
+            .description("Show the most common genres in the playlist")
+    });
+    commands.create_application_command(|command| {
+        command
+            .name("status")
+            .description("Show Spotify request latency and failures")
+            .dm_permission(false)
+    });
+    commands.create_application_command(|command| {
+        command
+            .name("admin")
+            .description("Manage the bot")
+            .dm_permission(false)
+            .create_option(|option| {
+                option
+                    .name("follow")
+                    .description(
+                        "Announce tracks added to an external Spotify playlist",
+                    )
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("playlist")
+                            .description("Spotify playlist URL or ID")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("import")
+                            .description(
+                                "Also add new tracks to the collaborative playlist",
+                            )
+                            .kind(CommandOptionType::Boolean)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("unfollow")
+                    .description("Stop following an external Spotify playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("playlist")
+                            .description("Spotify playlist URL or ID")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("dedupe")
+                    .description(
+                        "Find duplicate tracks in the collaborative playlist",
+                    )
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("confirm")
+                            .description(
+                                "Remove the duplicates instead of listing them",
+                            )
+                            .kind(CommandOptionType::Boolean)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("sort")
+                    .description("Reorder the collaborative playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("by")
+                            .description("What to sort the playlist by")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("Date added", "date_added")
+                            .add_string_choice("Artist", "artist")
+                            .add_string_choice("Popularity", "popularity")
+                            .add_string_choice("Duration", "duration")
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("feedback")
+                    .description("Choose how the bot responds to links")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("mode")
+                            .description("How much the bot says")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("Full replies", "full")
+                            .add_string_choice("Reactions only", "reactions")
+                            .add_string_choice("Silent", "silent")
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("block")
+                    .description("Keep an artist's tracks or a member's links out of the playlist")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("artist")
+                            .description("Artist name")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("user")
+                            .description("Member")
+                            .kind(CommandOptionType::User)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("unblock")
+                    .description("Allow a blocked artist or member again")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("artist")
+                            .description("Artist name")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("user")
+                            .description("Member")
+                            .kind(CommandOptionType::User)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("health")
+                    .description(
+                        "Report unplayable, duplicate, obscure and very long tracks",
+                    )
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("filters")
+                    .description("List the blocked artists and members")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("usage")
+                    .description("Show how often each command ran and failed")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("log")
+                    .description("Change what the bot logs until it restarts")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("filter")
+                            .description("Such as info,sonic::spotify_client=debug; leave out to reset")
+                            .kind(CommandOptionType::String)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("permissions")
+                    .description("Show or change who may use each command")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("command")
+                            .description("The command to change")
+                            .kind(CommandOptionType::String);
+                        for (name, _) in permissions::COMMANDS {
+                            option.add_string_choice(format!("/{name}"), *name);
+                        }
+                        option
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("role")
+                            .description("Role the command requires; leave out to reset")
+                            .kind(CommandOptionType::Role)
+                    })
+            })
+    })
+}
+
+/// Replaces a slash command's deferred reply, which everyone in the channel
+/// can see, with one only the member who ran the command can see. Discord
+/// fixes whether a reply is private when it is deferred, so this takes a
+/// new follow-up message.
+async fn reply_privately(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    content: String,
+) {
+    if let Err(why) = command
+        .delete_original_interaction_response(&ctx.http)
+        .await
+    {
+        error!("Cannot remove slash command reply: {:?}", why);
+    }
+    if let Err(why) = command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .content(content)
+                .ephemeral(true)
+                .allowed_mentions(|mentions| mentions.empty_parse())
+        })
+        .await
+    {
+        error!("Cannot respond to slash command: {:?}", why);
+    }
+}
+
+/// Reads a playlist with `read` and returns what it read along with the
+/// snapshot ID it matches. The playlist lock only holds off the bot's own
+/// commands, so the snapshot is read before and after and an edit by a
+/// collaborator in between is refused: positions sent under the wrong
+/// snapshot would move or remove the wrong tracks.
+fn read_at_snapshot<T, E: std::fmt::Debug>(
+    spotify_client: &mut spotify_client::SpotifyClient,
+    playlist_id: &str,
+    read: impl FnOnce(&mut spotify_client::SpotifyClient) -> Result<T, E>,
+) -> Result<(String, T), String> {
+    let unreadable = |why: &dyn std::fmt::Debug| {
+        error!("Could not fetch playlist {}: {:?}", playlist_id, why);
+        "Could not read the collaborative playlist".to_string()
+    };
+    let before = spotify_client
+        .get_playlist_snapshot(playlist_id)
+        .map_err(|why| unreadable(&why))?;
+    let read = read(spotify_client).map_err(|why| unreadable(&why))?;
+    let after = spotify_client
+        .get_playlist_snapshot(playlist_id)
+        .map_err(|why| unreadable(&why))?;
+    if before.snapshot_id != after.snapshot_id {
+        return Err("The playlist changed while it was being read, \
+                    try again"
+            .to_string());
+    }
+    Ok((after.snapshot_id, read))
+}
+
+/// Removes `(URI, position)` pairs from the collaborative playlist. `what`
+/// names the removed tracks in the reply.
+fn remove_from_playlist(
+    spotify_client: &spotify_client::SpotifyClient,
+    snapshot_id: String,
+    mut removals: Vec<(String, usize)>,
+    what: &str,
+) -> CommandResult {
+    // Remove from the end of the playlist first, so the positions of the
+    // remaining tracks are unaffected by earlier batches
+    removals.sort_by_key(|(_, position)| Reverse(*position));
+
+    let mut snapshot_id = snapshot_id;
+    let mut removed = 0;
+    for batch in removals.chunks(REMOVAL_BATCH_SIZE) {
+        match spotify_client.remove_playlist_positions(
+            spotify_client::PLAYLIST_ID,
+            &snapshot_id,
+            batch,
+        ) {
+            Ok(new_snapshot_id) => {
+                snapshot_id = new_snapshot_id;
+                removed += batch.len();
+            }
+            Err(why) => {
+                error!("Could not remove {}: {:?}", what, why);
+                return Err(format!(
+                    "Removed {} of {} {} before Spotify rejected the rest; \
+                     run the preview again to check",
+                    removed,
+                    removals.len(),
+                    what
+                ));
+            }
+        }
+    }
+    Ok(format!("Removed {} {}", removed, what))
+}
+
+/// The first few of a health report section's tracks, one line each.
+fn health_examples(tracks: &[(usize, TrackInfo)]) -> Vec<String> {
+    tracks
+        .iter()
+        .take(MAX_HEALTH_EXAMPLES)
+        .map(|(position, track)| {
+            format!(
+                "• #{} {} — {}",
+                position + 1,
+                track.name,
+                track.artists.join(", ")
+            )
+        })
+        .collect()
+}
+
+/// What to tell the member who pressed a button about the track it added.
+fn describe_outcome(outcome: &AddOutcome) -> String {
+    match outcome {
+        AddOutcome::Added(track) => {
+            format!("Added **{}** to the playlist", track.name)
+        }
+        AddOutcome::AlreadyInPlaylist(track) => {
+            format!("**{}** is already in the playlist", track.name)
+        }
+        AddOutcome::Unplayable(track) => format!(
+            "**{}** can't be played in the bot's Spotify market",
+            track.name
+        ),
+        AddOutcome::Blocked(track, artist) => format!(
+            "**{}** wasn't added, {} is blocked in this server",
+            track.name, artist
+        ),
+    }
+}
+
+/// What to tell a member about a failed Spotify request. `not_found` and
+/// `otherwise` are the messages for the failures specific to the request.
+fn describe_spotify_error(
+    why: &SpotifyError,
+    not_found: &str,
+    otherwise: &str,
+) -> String {
+    match why.feedback_kind() {
+        FeedbackKind::NotFound => not_found,
+        FeedbackKind::RateLimited => {
+            "Spotify is rate limiting the bot, try again in a minute"
+        }
+        FeedbackKind::Unavailable => {
+            "Spotify is not responding, try again later"
+        }
+        FeedbackKind::Forbidden => {
+            "The bot's Spotify account is not allowed to do that"
+        }
+        FeedbackKind::Internal => otherwise,
+    }
+    .to_string()
+}
+
+fn option_value<'a>(
+    options: &'a [CommandDataOption],
+    name: &str,
+) -> Option<&'a Value> {
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_ref())
+}
+
+/// Parses a tempo range such as `120-140` into its bounds in BPM.
+fn parse_bpm_range(input: &str) -> Option<(f64, f64)> {
+    let (min, max) = input.split_once('-')?;
+    let min: f64 = min.trim().parse().ok()?;
+    let max: f64 = max.trim().parse().ok()?;
+    (min > 0.0 && min <= max).then_some((min, max))
+}
+
+/// Every Spotify track linked in a message, in order. Links wrapped in `<>`
+/// to suppress Discord's preview count too.
+pub fn find_track_ids(content: &str) -> Vec<String> {
+    content
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('<').trim_end_matches('>'))
+        .filter(|word| Url::parse(word).is_ok())
+        .filter_map(|word| parse_spotify_id(word, "track"))
+        .collect()
+}
+
+/// The Spotify tracks of the Songlink pages linked in a message.
+fn resolve_songlink_urls(songlink: &Songlink, content: &str) -> Vec<String> {
+    songlink::find_songlink_urls(content)
+        .iter()
+        .filter_map(|url| match songlink.resolve_spotify_track(url) {
+            Ok(Some(track_id)) => Some(track_id),
+            Ok(None) => {
+                info!("{} has no Spotify track", url);
+                None
+            }
+            Err(why) => {
+                error!("Could not resolve {}: {:?}", url, why);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Spotify links in a message that can't go into a playlist, such as
+/// audiobooks and local files, described for a reply.
+fn find_unsupported_links(content: &str) -> Vec<&'static str> {
+    let mut kinds = Vec::new();
+    for word in content.split_whitespace() {
+        let word = word.trim_start_matches('<').trim_end_matches('>');
+        let kind = if word.starts_with("spotify:local:") {
+            "local files"
+        } else if Url::parse(word).is_ok()
+            && parse_spotify_id(word, "audiobook").is_some()
+        {
+            "audiobooks"
+        } else {
+            continue;
+        };
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+    kinds
+}
+
+/// Accepts either an `open.spotify.com/<kind>/...` URL or a bare Spotify ID.
+/// URLs of the embedded player (`/embed/<kind>/...`) and localized links
+/// (`/intl-de/<kind>/...`) are accepted too, and all reduce to the same ID.
+/// Anything that isn't a well-formed ID is refused, as IDs end up in API
+/// paths.
+fn parse_spotify_id(input: &str, kind: &str) -> Option<String> {
+    let id = match Url::parse(input) {
+        Ok(url) if url.host_str() != Some("open.spotify.com") => None,
+        Ok(url) => {
+            let mut segments =
+                url.path().split('/').skip(1).skip_while(|segment| {
+                    segment.starts_with("intl-") || *segment == "embed"
+                });
+            match (segments.next(), segments.next()) {
+                (Some(segment), Some(id))
+                    if segment == kind && !id.is_empty() =>
+                {
+                    Some(id.to_string())
+                }
+                _ => None,
+            }
+        }
+        Err(_) => Some(input.trim().to_string()),
+    };
+    id.filter(|id| {
+        id.len() == SPOTIFY_ID_LENGTH
+            && id.chars().all(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+fn parse_playlist_id(input: &str) -> Option<String> {
+    parse_spotify_id(input, "playlist")
+}
+
+async fn poll_followed_playlists(
+    ctx: Context,
+    mut spotify_client: spotify_client::SpotifyClient,
+    followed_playlists: FollowedPlaylists,
+    playlist_cache: Arc<PlaylistCache>,
+    blocklists: Blocklists,
+) {
+    let poll_interval = env_or(
+        "FOLLOW_POLL_INTERVAL_SECS",
+        DEFAULT_FOLLOW_POLL_INTERVAL_SECS,
+    );
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(poll_interval));
+    loop {
+        interval.tick().await;
+        let playlist_ids: Vec<String> =
+            followed_playlists.lock().await.keys().cloned().collect();
+        for playlist_id in playlist_ids {
+            check_followed_playlist(
+                &ctx,
+                &mut spotify_client,
+                &followed_playlists,
+                &playlist_cache,
+                &blocklists,
+                &playlist_id,
+            )
+            .await;
+        }
+    }
+}
+
+/// Announces the tracks added to a followed playlist since the last poll,
+/// and imports them if asked to. Imports go through the same checks as
+/// linked tracks: duplicates, the server's blocklist and playability.
+async fn check_followed_playlist(
+    ctx: &Context,
+    spotify_client: &mut spotify_client::SpotifyClient,
+    followed_playlists: &FollowedPlaylists,
+    playlist_cache: &PlaylistCache,
+    blocklists: &Blocklists,
+    playlist_id: &str,
+) {
+    let snapshot = match spotify_client.get_playlist_snapshot(playlist_id) {
+        Ok(snapshot) => snapshot,
+        Err(why) => {
+            error!("Could not poll playlist {}: {:?}", playlist_id, why);
+            return;
+        }
+    };
+    match followed_playlists.lock().await.get(playlist_id) {
+        Some(followed) if followed.snapshot_id != snapshot.snapshot_id => {}
+        // Unchanged, or unfollowed while we were polling
+        _ => return,
+    }
+
+    let tracks = match spotify_client.get_playlist_tracks(playlist_id) {
+        Ok(tracks) => tracks,
+        Err(why) => {
+            error!("Could not poll playlist {}: {:?}", playlist_id, why);
+            return;
+        }
+    };
+    let (name, channel_id, guild_id, import, new_tracks) = {
+        let mut playlists = followed_playlists.lock().await;
+        let followed = match playlists.get_mut(playlist_id) {
+            Some(followed) => followed,
+            None => return,
+        };
+        let new_tracks: Vec<spotify_client::TrackInfo> = tracks
+            .iter()
+            .filter(|track| !followed.track_uris.contains(&track.uri))
+            .cloned()
+            .collect();
+        followed.name = snapshot.name;
+        followed.snapshot_id = snapshot.snapshot_id;
+        followed.track_uris =
+            tracks.into_iter().map(|track| track.uri).collect();
+        (
+            followed.name.clone(),
+            followed.channel_id,
+            followed.guild_id,
+            followed.import,
+            new_tracks,
+        )
+    };
+    if new_tracks.is_empty() {
+        return;
+    }
+
+    let mut import_summary = None;
+    if import {
+        let blocklist = match guild_id {
+            Some(guild_id) => blocklists
+                .lock()
+                .await
+                .get(&guild_id)
+                .cloned()
+                .unwrap_or_default(),
+            None => Blocklist::default(),
+        };
+        let mut progress = Progress::start(
+            &ctx.http,
+            channel_id,
+            &format!("Importing from **{name}**"),
+            new_tracks.len(),
+        )
+        .await;
+        let (mut imported, mut duplicates, mut blocked, mut skipped) =
+            (0, 0, 0, 0);
+        for track in &new_tracks {
+            progress.advance().await;
+            // Local files have no Spotify ID to add them by
+            let Some(track_id) = track.uri.strip_prefix("spotify:track:")
+            else {
+                skipped += 1;
+                continue;
+            };
+            match playlist_cache.add_unless_present(
+                spotify_client,
+                &blocklist,
+                track_id,
+            ) {
+                Ok(AddOutcome::Added(_)) => imported += 1,
+                Ok(AddOutcome::AlreadyInPlaylist(_)) => duplicates += 1,
+                Ok(AddOutcome::Blocked(..)) => blocked += 1,
+                Ok(AddOutcome::Unplayable(_)) => skipped += 1,
+                Err(why) => {
+                    error!("Could not import track {}: {:?}", track.uri, why);
+                    skipped += 1;
+                }
+            }
+        }
+        progress.finish().await;
+        let mut summary = format!("Imported {imported}");
+        if duplicates > 0 {
+            summary
+                .push_str(&format!(", {duplicates} already in the playlist"));
+        }
+        if blocked > 0 {
+            summary.push_str(&format!(", {blocked} blocked"));
+        }
+        if skipped > 0 {
+            summary.push_str(&format!(", {skipped} couldn't be added"));
+        }
+        import_summary = Some(summary);
+    }
+
+    let mut announcement = format!("New in **{}**:", name);
+    for track in new_tracks.iter().take(MAX_LISTED_TRACKS) {
+        announcement.push_str(&format!(
+            "\n• {} — {}",
+            track.name,
+            track.artists.join(", ")
+        ));
+    }
+    if new_tracks.len() > MAX_LISTED_TRACKS {
+        announcement.push_str(&format!(
+            "\n…and {} more",
+            new_tracks.len() - MAX_LISTED_TRACKS
+        ));
+    }
+    if let Some(import_summary) = import_summary {
+        announcement.push_str(&format!("\n{import_summary}"));
+    }
+    let ping_role = env_or("FOLLOW_ANNOUNCEMENT_PING", true);
+    announce(ctx, channel_id, announcement, ping_role).await;
+}
+
+/// Reveals the answer once a quiz round runs out of time without a winner.
+async fn expire_quiz_round(
+    ctx: Context,
+    quiz: Arc<Mutex<Quiz>>,
+    channel_id: ChannelId,
+    round_id: u64,
+    duration: Duration,
+) {
+    tokio::time::sleep(duration).await;
+    let track = match quiz.lock().await.expire(channel_id, round_id) {
+        Some(track) => track,
+        None => return,
+    };
+    let content = format!(
+        "⏰ Time's up! It was **{}** — {}.",
+        track.name,
+        track.artists.join(", ")
+    );
+    if let Err(why) = channel_id.say(&ctx.http, content).await {
+        error!("Could not reveal the quiz answer: {:?}", why);
+    }
+}
+
+/// Celebrates every `MILESTONE_STEP`th track (default 100, 0 to disable)
+/// added to the collaborative playlist.
+async fn announce_milestone(
+    ctx: &Context,
+    msg: &Message,
+    track: &spotify_client::TrackInfo,
+    track_count: usize,
+) {
+    let step = env_or("MILESTONE_STEP", DEFAULT_MILESTONE_STEP);
+    if step == 0 || track_count == 0 || !track_count.is_multiple_of(step) {
+        return;
+    }
+    if let Err(why) = msg
+        .channel_id
+        .send_message(&ctx.http, |message| {
+            message
+                .embed(|embed| {
+                    embed
+                        .title(format!(
+                            "🎉 {} track added — by {}!",
+                            ordinal(track_count),
+                            msg.author.name
+                        ))
+                        .description(format!(
+                            "[{}]({}) — {}",
+                            track.name,
+                            track.url(),
+                            track.artists.join(", ")
+                        ))
+                        .colour(MILESTONE_COLOUR);
+                    if let Some(image) = &track.album_image_url {
+                        embed.thumbnail(image);
+                    }
+                    embed
+                })
+                .content(msg.author.mention())
+                .allowed_mentions(|mentions| mentions.users([msg.author.id]))
+        })
+        .await
+    {
+        error!("Could not announce milestone: {:?}", why);
+    }
+}
+
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
+/// Announces new albums and singles by the artists featured most in the
+/// collaborative playlist, checking every `NEW_RELEASES_POLL_INTERVAL_SECS`.
+/// The first check only records what is already out. Later ones announce
+/// whatever wasn't seen before, so releases are announced again after a
+/// restart only if they came out while the bot was down.
+async fn watch_new_releases(
+    ctx: Context,
+    mut spotify_client: spotify_client::SpotifyClient,
+    channel_id: ChannelId,
+) {
+    let poll_interval = env_or(
+        "NEW_RELEASES_POLL_INTERVAL_SECS",
+        DEFAULT_NEW_RELEASES_POLL_INTERVAL_SECS,
+    );
+    let artist_count =
+        env_or("NEW_RELEASES_TOP_ARTISTS", DEFAULT_NEW_RELEASES_TOP_ARTISTS);
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(poll_interval));
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut first_check = true;
+    loop {
+        interval.tick().await;
+        let tracks = match spotify_client
+            .get_playlist_tracks(spotify_client::PLAYLIST_ID)
+        {
+            Ok(tracks) => tracks,
+            Err(why) => {
+                error!("Could not fetch the playlist's artists: {:?}", why);
+                continue;
+            }
+        };
+        let mut new_releases = Vec::new();
+        for (artist_id, artist_name) in
+            playlist_manager::top_artists(&tracks, artist_count)
+        {
+            let releases = match spotify_client.get_artist_releases(&artist_id)
+            {
+                Ok(releases) => releases,
+                Err(why) => {
+                    error!(
+                        "Could not fetch releases of {}: {:?}",
+                        artist_name, why
+                    );
+                    continue;
+                }
+            };
+            for release in releases {
+                // Collaborations come up once per featured artist
+                if seen.insert(release.id.clone()) && !first_check {
+                    new_releases.push((artist_name.clone(), release));
+                }
+            }
+        }
+        first_check = false;
+        for (artist_name, release) in new_releases {
+            announce_release(&ctx, channel_id, &artist_name, &release).await;
+        }
+    }
+}
+
+async fn announce_release(
+    ctx: &Context,
+    channel_id: ChannelId,
+    artist_name: &str,
+    release: &spotify_client::Release,
+) {
+    if let Err(why) = channel_id
+        .send_message(&ctx.http, |message| {
+            message
+                .embed(|embed| {
+                    embed
+                        .title(format!(
+                            "New release from {artist_name}, a server favourite"
+                        ))
+                        .description(format!(
+                            "[{}]({}) — {}\n{} out {}",
+                            release.name,
+                            release.url(),
+                            release.artists.join(", "),
+                            release.kind,
+                            release.release_date
+                        ));
+                    if let Some(image) = &release.image_url {
+                        embed.thumbnail(image);
+                    }
+                    embed
+                })
+                .components(|components| {
+                    components.create_action_row(|row| {
+                        row.create_button(|button| {
+                            button
+                                .style(ButtonStyle::Primary)
+                                .label("Add lead track")
+                                .custom_id(format!(
+                                    "{ADD_RELEASE_ID}{}",
+                                    release.id
+                                ))
+                        })
+                    })
+                })
+        })
+        .await
+    {
+        error!("Could not announce release: {:?}", why);
+    }
+}
+
+/// Waits for an event to run out, then stops routing its channel's links and
+/// renames its playlist to mark it as archived.
+async fn end_event(
+    ctx: Context,
+    spotify_client: spotify_client::SpotifyClient,
+    events: EventPlaylists,
+    channel_id: ChannelId,
+    playlist_id: String,
+    duration: Duration,
+) {
+    tokio::time::sleep(duration).await;
+    let event = {
+        let mut events = events.lock().await;
+        // Only end the event this timer was started for
+        let is_current = events
+            .get(&channel_id)
+            .is_some_and(|event| event.playlist_id == playlist_id);
+        if !is_current {
+            return;
+        }
+        match events.remove(&channel_id) {
+            Some(event) => event,
+            None => return,
+        }
+    };
+    info!("Event {} ended", event.name);
+    let archived_name = format!("{} (archived)", event.name);
+    if let Err(why) =
+        spotify_client.rename_playlist(&playlist_id, &archived_name)
+    {
+        error!("Could not archive event playlist {}: {}", playlist_id, why);
+    }
+    let content = format!(
+        "**{}** has ended with {} tracks: {}\nLinks posted here go to the \
+         collaborative playlist again",
+        event.name,
+        event.track_uris.len(),
+        spotify_client::playlist_url(&playlist_id)
+    );
+    if let Err(why) = channel_id.say(&ctx.http, content).await {
+        error!("Could not announce the end of an event: {:?}", why);
+    }
+}
+
+/// Forwards a message's rejected tracks to `QUARANTINE_CHANNEL_ID`, when set,
+/// in one embed with a link to the message and a button per track for
+/// moderators to add it anyway. Only the first `MAX_QUARANTINED_TRACKS` are
+/// listed.
+async fn quarantine(
+    ctx: &Context,
+    msg: &Message,
+    track_ids: &[&str],
+    reason: &str,
+) {
+    let channel_id = env_or("QUARANTINE_CHANNEL_ID", 0u64);
+    if channel_id == 0 || track_ids.is_empty() {
+        return;
+    }
+    let shown = &track_ids[..track_ids.len().min(MAX_QUARANTINED_TRACKS)];
+    let mut tracks: Vec<String> = shown
+        .iter()
+        .enumerate()
+        .map(|(i, track_id)| {
+            format!("{}. {}", i + 1, spotify_client::track_url(track_id))
+        })
+        .collect();
+    if track_ids.len() > shown.len() {
+        tracks.push(format!("…and {} more", track_ids.len() - shown.len()));
+    }
+    let label = |i: usize| match shown.len() {
+        1 => "Add anyway".to_string(),
+        _ => format!("Add {} anyway", i + 1),
+    };
+    if let Err(why) = ChannelId(channel_id)
+        .send_message(&ctx.http, |message| {
+            message
+                .embed(|embed| {
+                    embed
+                        .title("Rejected submission")
+                        .description(reason)
+                        .field(
+                            if shown.len() == 1 { "Track" } else { "Tracks" },
+                            tracks.join("\n"),
+                            false,
+                        )
+                        .field("Posted by", msg.author.mention(), true)
+                        .field("Message", msg.link(), true)
+                })
+                .components(|components| {
+                    let rows = shown.chunks(BUTTONS_PER_ROW);
+                    for (row_index, row_ids) in rows.enumerate() {
+                        components.create_action_row(|row| {
+                            for (offset, track_id) in row_ids.iter().enumerate()
+                            {
+                                let i = row_index * BUTTONS_PER_ROW + offset;
+                                row.create_button(|button| {
+                                    button
+                                        .style(ButtonStyle::Danger)
+                                        .label(label(i))
+                                        .custom_id(format!(
+                                            "{OVERRIDE_ADD_ID}{track_id}"
+                                        ))
+                                });
+                            }
+                            row
+                        });
+                    }
+                    components
+                })
+                // Moderators should see who posted without pinging them
+                .allowed_mentions(|mentions| mentions.empty_parse())
+        })
+        .await
+    {
+        error!("Could not quarantine submission: {:?}", why);
+    }
+}
+
+/// Tells moderators that a member's links look like spam and are being
+/// ignored, in `MODERATION_CHANNEL_ID` or else `QUARANTINE_CHANNEL_ID`.
+/// Chats other than Discord have no `message_link` to give.
+pub async fn alert_moderators(
+    http: &Http,
+    posted_by: String,
+    message_link: Option<String>,
+    flagged: &moderation::Flagged,
+) {
+    let channel_id = env_or(
+        "MODERATION_CHANNEL_ID",
+        env_or("QUARANTINE_CHANNEL_ID", 0u64),
+    );
+    if channel_id == 0 {
+        return;
+    }
+    let muted = if flagged.muted_for.is_zero() {
+        "Not muted".to_string()
+    } else {
+        let muted_until = (SystemTime::now() + flagged.muted_for)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        format!("Links ignored until <t:{muted_until}:t>")
+    };
+    if let Err(why) = ChannelId(channel_id)
+        .send_message(http, |message| {
+            message
+                .embed(|embed| {
+                    embed
+                        .title("Possible link spam")
+                        .description(&flagged.reason)
+                        .field("Posted by", posted_by, true);
+                    if let Some(message_link) = message_link {
+                        embed.field("Message", message_link, true);
+                    }
+                    embed.field("Mute", muted, false)
+                })
+                .allowed_mentions(|mentions| mentions.empty_parse())
+        })
+        .await
+    {
+        error!("Could not alert moderators: {:?}", why);
+    }
+}
+
+/// Posts a track from the collaborative playlist to `channel_id` every day at
+/// `SONG_OF_THE_DAY_HOUR` UTC, skipping tracks picked in the last
+/// `SONG_OF_THE_DAY_REPEAT_DAYS` days. The history is lost on restart.
+async fn post_songs_of_the_day(
+    ctx: Context,
+    mut spotify_client: spotify_client::SpotifyClient,
+    album_colours: AlbumColours,
+    channel_id: ChannelId,
+) {
+    let hour = env_or("SONG_OF_THE_DAY_HOUR", DEFAULT_SONG_OF_THE_DAY_HOUR);
+    let repeat_days = env_or(
+        "SONG_OF_THE_DAY_REPEAT_DAYS",
+        DEFAULT_SONG_OF_THE_DAY_REPEAT_DAYS,
+    );
+    let mut history: VecDeque<String> = VecDeque::new();
+    loop {
+        tokio::time::sleep(time_until_hour(hour)).await;
+        let items = match spotify_client
+            .get_playlist_items(spotify_client::PLAYLIST_ID)
+        {
+            Ok(items) => items,
+            Err(why) => {
+                error!("Could not pick a song of the day: {:?}", why);
+                continue;
+            }
+        };
+        let recent: HashSet<String> = history.iter().cloned().collect();
+        let item = playlist_manager::pick_weighted_by_recency(
+            items,
+            &recent,
+            &mut rand::thread_rng(),
+        );
+        let (track, added_at, added_by) = match item {
+            Some(PlaylistItem {
+                track: Some(track),
+                added_at,
+                added_by,
+                ..
+            }) => (track, added_at, added_by),
+            _ => {
+                info!("No track left to pick as song of the day");
+                continue;
+            }
+        };
+        history.push_back(track.uri.clone());
+        while history.len() > repeat_days {
+            history.pop_front();
+        }
+
+        let mut blurb = format!("by {}", track.artists.join(", "));
+        if let Some(date) = added_at.as_deref().and_then(|at| at.get(..10)) {
+            blurb.push_str(&format!("\nAdded to the playlist on {date}"));
+        }
+        if let Some(user_id) = added_by {
+            // Tracks added in the Spotify app have no Discord member to credit
+            let name = spotify_client
+                .get_user_display_name(&user_id)
+                .map_err(|why| {
+                    error!(
+                        "Could not look up Spotify user {}: {}",
+                        user_id, why
+                    )
+                })
+                .ok()
+                .unwrap_or_else(|| user_id.clone());
+            blurb.push_str(&format!(
+                " by [{}]({})",
+                name,
+                spotify_client::user_url(&user_id)
+            ));
+        }
+        let colour = track
+            .album_image_url
+            .as_deref()
+            .and_then(|image_url| album_colours.get(image_url));
+        if let Err(why) = channel_id
+            .send_message(&ctx.http, |message| {
+                message.embed(|embed| {
+                    embed
+                        .title(format!("Song of the day: {}", track.name))
+                        .url(track.url())
+                        .description(blurb);
+                    if let Some(image) = &track.album_image_url {
+                        embed.thumbnail(image);
+                    }
+                    if let Some(colour) = colour {
+                        embed.colour(colour);
+                    }
+                    embed
+                })
+            })
+            .await
+        {
+            error!("Could not post the song of the day: {:?}", why);
+        }
+    }
+}
+
+/// Time until the clock next reads `hour`:00 UTC, a full day if it is that
+/// time right now.
+fn time_until_hour(hour: u64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is set before 1970")
+        .as_secs();
+    let target = hour * 60 * 60 % SECS_PER_DAY;
+    let elapsed_today = now % SECS_PER_DAY;
+    Duration::from_secs(
+        (target + SECS_PER_DAY - elapsed_today - 1) % SECS_PER_DAY + 1,
+    )
+}
+
+/// Posts an announcement, mentioning the `ANNOUNCEMENT_ROLE_ID` role first
+/// when one is configured and `ping_role` is set. That role is the only
+/// mention Discord will act on, so track and playlist names can never ping
+/// @everyone or individual members.
+///
+/// Long announcements are split over as many messages as it takes, and only
+/// the first pings the role. Serenity waits out Discord's rate limits, so a
+/// failure here is not worth retrying.
+async fn announce(
+    ctx: &Context,
+    channel_id: ChannelId,
+    content: String,
+    ping_role: bool,
+) {
+    let role_id = env_or("ANNOUNCEMENT_ROLE_ID", 0u64);
+    let role_id = (ping_role && role_id != 0).then_some(RoleId(role_id));
+    let content = match role_id {
+        Some(role_id) => format!("{}\n{}", role_id.mention(), content),
+        None => content,
+    };
+    let chunks = split_message(&content, MAX_MESSAGE_CHARS);
+    for (sent, chunk) in chunks.iter().enumerate() {
+        let role_id = role_id.filter(|_| sent == 0);
+        if let Err(why) = channel_id
+            .send_message(&ctx.http, |message| {
+                message.content(chunk).allowed_mentions(|mentions| {
+                    mentions.empty_parse().roles(role_id)
+                })
+            })
+            .await
+        {
+            // The rest would read out of context without this part
+            error!(
+                "Could not send announcement after {} of {} messages: {:?}",
+                sent,
+                chunks.len(),
+                why
+            );
+            return;
+        }
     }
 }
 
-pub async fn start_bot() {
+/// The command and subcommand that was run, such as `/admin health`.
+fn command_label(command: &ApplicationCommandInteraction) -> String {
+    let mut label = format!("/{}", command.data.name);
+    if let Some(subcommand) = command
+        .data
+        .options
+        .first()
+        .filter(|option| option.kind == CommandOptionType::SubCommand)
+    {
+        label.push(' ');
+        label.push_str(&subcommand.name);
+    }
+    label
+}
+
+/// One line of `/status` or `/admin usage`, such as "`/discover` — 12 runs,
+/// 8.3% failed, p50 ≤1000 ms, p95 ≤2500 ms".
+fn format_stats(name: &str, stats: &EndpointStats, noun: &str) -> String {
+    let format_latency = |latency_ms: Option<u64>| match latency_ms {
+        Some(latency_ms) => format!("≤{latency_ms} ms"),
+        None => "slower than 5 s".to_string(),
+    };
+    format!(
+        "`{}` — {} {}, {:.1}% failed, p50 {}, p95 {}",
+        name,
+        stats.requests,
+        noun,
+        stats.error_rate() * 100.0,
+        format_latency(stats.latency_percentile_ms(50.0)),
+        format_latency(stats.latency_percentile_ms(95.0)),
+    )
+}
+
+/// Formats a track length as minutes and seconds, such as `3:07`.
+fn format_duration(duration_ms: u64) -> String {
+    let secs = duration_ms / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Splits `content` into pieces of at most `max_chars` characters, between
+/// lines where possible.
+fn split_message(content: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_chars = 0;
+    for line in content.lines() {
+        let mut line_chars = line.chars().count();
+        let mut line = line;
+        // Lines too long for a message of their own are cut anywhere
+        while line_chars > max_chars {
+            if !chunk.is_empty() {
+                chunks.push(std::mem::take(&mut chunk));
+                chunk_chars = 0;
+            }
+            let cut = line
+                .char_indices()
+                .nth(max_chars)
+                .map_or(line.len(), |(index, _)| index);
+            chunks.push(line[..cut].to_string());
+            line = &line[cut..];
+            line_chars -= max_chars;
+        }
+        let separator = usize::from(!chunk.is_empty());
+        if chunk_chars + separator + line_chars > max_chars {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_chars = 0;
+        }
+        if !chunk.is_empty() {
+            chunk.push('\n');
+            chunk_chars += 1;
+        }
+        chunk.push_str(line);
+        chunk_chars += line_chars;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+pub async fn start_bot(logger: LoggerHandle) {
     // Configure the client with your Discord bot token in the environment.
-    let token =
-        env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+    let token = secrets::read("DISCORD_TOKEN")
+        .expect("Expected a token in the environment");
     // Set gateway intents, which decides what events the bot will be notified about
-    let intents = GatewayIntents::GUILD_MESSAGES
-        | GatewayIntents::DIRECT_MESSAGES
-        | GatewayIntents::MESSAGE_CONTENT;
+    let intents = gateway_intents();
 
     // Create a new instance of the Client, logging in as a bot. This will
     // automatically prepend your bot token with "Bot ", which is a requirement
     // by Discord for bot users.
     let mut client = Client::builder(&token, intents)
-        .event_handler(Handler {
-            spotify_client: spotify_client::SpotifyClient::new(),
-        })
+        .event_handler(Handler::new(http_client::build(), logger))
         .await
         .expect("Err creating client");
 
-    // let mut client = Client::builder(&token, intents)
-    //     .event_handler(Handler::new())
-    //     .await
-    //     .expect("Err creating client");
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(systemd::run_watchdog(shard_manager.clone()));
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        info!("Shutting down");
+        systemd::notify_stopping();
+        shard_manager.lock().await.shutdown_all().await;
+    });
 
     if let Err(why) = client.start().await {
+        if let SerenityError::Gateway(GatewayError::DisallowedGatewayIntents) =
+            why
+        {
+            error!(
+                "Discord refused the gateway intents, enable them for the \
+                 bot in the Discord developer portal"
+            );
+        }
         error!("Client error: {:?}", why);
     }
 }
+
+/// The gateway intents to connect with. Reading links from messages needs
+/// the privileged message content intent; deployments that only use slash
+/// commands can set `MESSAGE_CONTENT_INTENT=false` to go without it.
+fn gateway_intents() -> GatewayIntents {
+    let intents =
+        GatewayIntents::GUILD_MESSAGES | GatewayIntents::DIRECT_MESSAGES;
+    if env_or("MESSAGE_CONTENT_INTENT", true) {
+        intents | GatewayIntents::MESSAGE_CONTENT
+    } else {
+        intents
+    }
+}
+
+/// Warns when the bot asks for message content that Discord won't send,
+/// since it would then see every message as empty and never add a link.
+fn check_message_content_intent(ready: &Ready) {
+    if !gateway_intents().contains(GatewayIntents::MESSAGE_CONTENT) {
+        info!("Message content intent disabled, only slash commands work");
+        return;
+    }
+    let flags = ready.application.flags.bits();
+    if flags & (MESSAGE_CONTENT_FLAG | MESSAGE_CONTENT_LIMITED_FLAG) == 0 {
+        error!(
+            "The message content intent is not enabled for this bot, so \
+             links posted in servers will be ignored. Enable \"Message \
+             Content Intent\" on the Bot page of the Discord developer \
+             portal, or set MESSAGE_CONTENT_INTENT=false to only use slash \
+             commands"
+        );
+    }
+}
+
+pub async fn wait_for_shutdown_signal() {
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("Could not listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const TRACK_ID: &str = "4uLU6hMCjMI75M1A2tKUQC";
+    const SECOND_ID: &str = "7ouMYWpwJ422jRcDASZB7P";
+
+    #[test]
+    fn parse_spotify_id_accepts_track_urls() {
+        for url in [
+            format!("https://open.spotify.com/track/{TRACK_ID}"),
+            format!("https://open.spotify.com/track/{TRACK_ID}?si=abc"),
+            format!("https://open.spotify.com/intl-de/track/{TRACK_ID}"),
+            format!("https://open.spotify.com/embed/track/{TRACK_ID}"),
+        ] {
+            assert_eq!(
+                parse_spotify_id(&url, "track").as_deref(),
+                Some(TRACK_ID),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_spotify_id_rejects_other_urls() {
+        for url in [
+            format!("https://example.com/track/{TRACK_ID}"),
+            format!("https://open.spotify.com/album/{TRACK_ID}"),
+            "https://open.spotify.com/track".to_string(),
+            "https://open.spotify.com/track/".to_string(),
+            "https://open.spotify.com/".to_string(),
+            "https://open.spotify.com/intl-de/embed".to_string(),
+        ] {
+            assert_eq!(parse_spotify_id(&url, "track"), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn parse_spotify_id_trims_bare_ids() {
+        assert_eq!(
+            parse_spotify_id(&format!(" {TRACK_ID}\n"), "track").as_deref(),
+            Some(TRACK_ID)
+        );
+    }
+
+    #[test]
+    fn parse_spotify_id_rejects_malformed_ids() {
+        for input in [
+            "abc/../../me".to_string(),
+            "foo?x".to_string(),
+            "4uLU6hMCjMI75M1A2tKUQ".to_string(),
+            format!("{TRACK_ID}x"),
+            "4uLU6hMCjMI75M1A2tKU-C".to_string(),
+            "https://open.spotify.com/track/abc".to_string(),
+            format!("https://open.spotify.com/track/{TRACK_ID}%2F.."),
+        ] {
+            assert_eq!(parse_spotify_id(&input, "track"), None, "{input}");
+        }
+    }
+
+    #[test]
+    fn find_track_ids_handles_odd_whitespace_and_brackets() {
+        let content = format!(
+            "look\t<https://open.spotify.com/track/{TRACK_ID}>\r\n\
+             https://open.spotify.com/track/{SECOND_ID}\u{a0}end"
+        );
+        assert_eq!(find_track_ids(&content), [TRACK_ID, SECOND_ID]);
+    }
+
+    #[test]
+    fn find_track_ids_ignores_malformed_urls() {
+        let content = format!(
+            "{TRACK_ID} https:// http://[::1 open.spotify.com/track/{TRACK_ID} \
+             https://open.spotify.com/track <> <https://open.spotify.com/>"
+        );
+        assert!(find_track_ids(&content).is_empty());
+    }
+
+    #[test]
+    fn find_unsupported_links_describes_each_kind_once() {
+        let content = format!(
+            "spotify:local:a:b:c:1 \
+             <https://open.spotify.com/audiobook/{SECOND_ID}> \
+             spotify:local:d:e:f:2 \
+             https://open.spotify.com/track/{TRACK_ID}"
+        );
+        assert_eq!(
+            find_unsupported_links(&content),
+            ["local files", "audiobooks"]
+        );
+        let track_link = format!("https://open.spotify.com/track/{TRACK_ID}");
+        assert!(find_unsupported_links(&track_link).is_empty());
+    }
+
+    #[test]
+    fn split_message_cuts_multibyte_characters_whole() {
+        assert_eq!(split_message("ééééé", 3), ["ééé", "éé"]);
+        assert_eq!(split_message("a🎵b🎵c", 2), ["a🎵", "b🎵", "c"]);
+    }
+
+    #[test]
+    fn split_message_cuts_long_lines_anywhere() {
+        assert_eq!(split_message("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(
+            split_message("ab\n123456789\ncd", 4),
+            ["ab", "1234", "5678", "9\ncd"]
+        );
+    }
+
+    #[test]
+    fn split_message_at_exact_boundaries() {
+        assert_eq!(split_message("abcde", 5), ["abcde"]);
+        assert_eq!(split_message("abcdefghij", 5), ["abcde", "fghij"]);
+        // The newline joining two lines counts towards the limit
+        assert_eq!(split_message("ab\ncd", 5), ["ab\ncd"]);
+        assert_eq!(split_message("ab\ncde", 5), ["ab", "cde"]);
+        assert!(split_message("", 5).is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn split_message_keeps_every_character_within_limit(
+            content in "[a-zé🎵\n]{0,60}",
+            max_chars in 1usize..10,
+        ) {
+            let chunks = split_message(&content, max_chars);
+            for chunk in &chunks {
+                prop_assert!(chunk.chars().count() <= max_chars);
+            }
+            let kept: String =
+                chunks.concat().chars().filter(|c| *c != '\n').collect();
+            let expected: String =
+                content.chars().filter(|c| *c != '\n').collect();
+            prop_assert_eq!(kept, expected);
+        }
+
+        #[test]
+        fn link_parsing_never_panics(content in "\\PC*") {
+            find_track_ids(&content);
+            find_unsupported_links(&content);
+            parse_spotify_id(&content, "track");
+        }
+
+        #[test]
+        fn find_track_ids_finds_every_link_in_order(
+            links in prop::collection::vec(
+                ("[0-9A-Za-z]{22}", any::<bool>()),
+                0..8,
+            ),
+            separators in prop::collection::vec(
+                prop::sample::select(
+                    vec![" ", "\t", "\n", "\r\n", "\u{a0}", "   "],
+                ),
+                8,
+            ),
+        ) {
+            let mut content = String::new();
+            for ((track_id, bracketed), separator) in
+                links.iter().zip(&separators)
+            {
+                let url =
+                    format!("https://open.spotify.com/track/{track_id}");
+                if *bracketed {
+                    content.push_str(&format!("<{url}>"));
+                } else {
+                    content.push_str(&url);
+                }
+                content.push_str(separator);
+            }
+            let track_ids: Vec<String> =
+                links.into_iter().map(|(track_id, _)| track_id).collect();
+            prop_assert_eq!(find_track_ids(&content), track_ids);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::Read;
+
+use reqwest::blocking::Client;
+use url::Url;
+
+// Track pages are a few hundred kilobytes, the metadata is near the top
+const MAX_PAGE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Reads what SoundCloud and Bandcamp track links point at, so the same
+/// track can be looked up on Spotify.
+#[derive(Clone)]
+pub struct ExternalLinks {
+    http_client: Client,
+}
+
+impl ExternalLinks {
+    pub fn new(http_client: Client) -> ExternalLinks {
+        ExternalLinks { http_client }
+    }
+
+    /// A Spotify search query for the track at `url`, built from the title
+    /// and artist in its page's metadata. `None` if the page has neither.
+    pub fn search_query(
+        &self,
+        url: &str,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let response = self.http_client.get(url).send()?.error_for_status()?;
+        let mut page = Vec::new();
+        response.take(MAX_PAGE_BYTES).read_to_end(&mut page)?;
+        let html = String::from_utf8_lossy(&page);
+        let is_bandcamp = Url::parse(url)?
+            .host_str()
+            .is_some_and(|host| host.ends_with("bandcamp.com"));
+        Ok(if is_bandcamp {
+            bandcamp_query(&html)
+        } else {
+            soundcloud_query(&html)
+        })
+    }
+}
+
+/// Every SoundCloud or Bandcamp track linked in a message, in order.
+pub fn find_external_links(content: &str) -> Vec<String> {
+    content
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('<').trim_end_matches('>'))
+        .filter(|word| Url::parse(word).is_ok_and(|url| is_track_link(&url)))
+        .map(String::from)
+        .collect()
+}
+
+fn is_track_link(url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default();
+    let segments: Vec<&str> =
+        url.path().split('/').filter(|s| !s.is_empty()).collect();
+    match host {
+        // Share links redirect to the track page
+        "on.soundcloud.com" => segments.len() == 1,
+        // Tracks live at /<user>/<track>, playlists at /<user>/sets/<set>
+        "soundcloud.com" | "www.soundcloud.com" | "m.soundcloud.com" => {
+            segments.len() == 2 && segments[1] != "sets"
+        }
+        _ => {
+            host.ends_with(".bandcamp.com")
+                && segments.first() == Some(&"track")
+        }
+    }
+}
+
+/// Bandcamp titles its track pages "Title, by Artist".
+fn bandcamp_query(html: &str) -> Option<String> {
+    let title = meta_content(html, "og:title")?;
+    Some(title.replacen(", by ", " ", 1))
+}
+
+/// SoundCloud titles its track pages "Stream Title by Artist | Listen online
+/// for free on SoundCloud".
+fn soundcloud_query(html: &str) -> Option<String> {
+    let title = title_text(html)?;
+    let title = title.strip_prefix("Stream ").unwrap_or(&title);
+    let title = title.split(" | ").next()?;
+    Some(match title.rsplit_once(" by ") {
+        Some((name, artist)) => format!("{name} {artist}"),
+        None => title.to_string(),
+    })
+}
+
+fn meta_content(html: &str, property: &str) -> Option<String> {
+    let at = html.find(&format!("property=\"{property}\""))?;
+    let start = html[..at].rfind('<')?;
+    let end = at + html[at..].find('>')?;
+    let (_, content) = html[start..end].split_once("content=\"")?;
+    let content = content.split('"').next()?;
+    Some(decode_entities(content))
+}
+
+fn title_text(html: &str) -> Option<String> {
+    let start = html.find("<title")?;
+    let text_start = start + html[start..].find('>')? + 1;
+    let text_end = text_start + html[text_start..].find("</title>")?;
+    Some(decode_entities(html[text_start..text_end].trim()))
+}
+
+/// Decodes the entities that show up in titles.
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,190 @@
+use std::borrow::Cow;
+use std::env;
+use std::fmt::Display;
+use std::time::Duration;
+
+use log::error;
+use serenity::model::channel::{AttachmentType, Message};
+use serenity::prelude::*;
+
+use crate::config::env_or;
+use crate::spotify_client::TrackInfo;
+
+/// How much the bot says in response to links posted in a server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeedbackMode {
+    /// Reply with embeds.
+    Full,
+    /// Only react to the message, for busy channels.
+    Reactions,
+    /// Say nothing, errors are only logged.
+    Silent,
+}
+
+impl FeedbackMode {
+    pub fn from_name(name: &str) -> Option<FeedbackMode> {
+        match name {
+            "full" => Some(FeedbackMode::Full),
+            "reactions" => Some(FeedbackMode::Reactions),
+            "silent" => Some(FeedbackMode::Silent),
+            _ => None,
+        }
+    }
+
+    /// The mode for servers that have not picked one, from `FEEDBACK_MODE`.
+    /// Read once at startup, after `config::validate` has refused a bad
+    /// value.
+    pub fn default_from_env() -> FeedbackMode {
+        env::var("FEEDBACK_MODE")
+            .ok()
+            .map(|name| {
+                FeedbackMode::from_name(&name)
+                    .expect("FEEDBACK_MODE must be full, reactions or silent")
+            })
+            .unwrap_or(FeedbackMode::Full)
+    }
+}
+
+pub const ADDED_REACTION: char = '✅';
+pub const DUPLICATE_REACTION: char = '🔁';
+pub const FAILED_REACTION: char = '❌';
+
+/// Reacts to the message that triggered an action.
+pub async fn react(ctx: &Context, msg: &Message, emoji: char) {
+    if let Err(why) = msg.react(&ctx.http, emoji).await {
+        error!("Could not react with feedback: {:?}", why);
+    }
+}
+
+/// Answers the message that triggered an action as loudly as `mode` allows:
+/// with `content` as a reply, only `reaction`, or not at all.
+pub async fn respond(
+    ctx: &Context,
+    msg: &Message,
+    mode: FeedbackMode,
+    reaction: char,
+    content: impl Display,
+) {
+    match mode {
+        FeedbackMode::Full => reply_error(ctx, msg, content).await,
+        FeedbackMode::Reactions => react(ctx, msg, reaction).await,
+        FeedbackMode::Silent => {}
+    }
+}
+
+/// Like `respond`, for an action taken on several things at once. Reactions
+/// mode adds each of `reactions` whose flag is set.
+pub async fn respond_summary(
+    ctx: &Context,
+    msg: &Message,
+    mode: FeedbackMode,
+    reactions: &[(bool, char)],
+    summary: impl Display,
+    details: impl Display,
+) {
+    match mode {
+        FeedbackMode::Full => reply_summary(ctx, msg, summary, details).await,
+        FeedbackMode::Reactions => {
+            for (_, emoji) in reactions.iter().filter(|(show, _)| *show) {
+                react(ctx, msg, *emoji).await;
+            }
+        }
+        FeedbackMode::Silent => {}
+    }
+}
+
+/// Confirms that `track` was added with an embed showing its album cover,
+/// tinted with `colour` when the cover's colour is known. A `preview` clip is
+/// attached so members can listen without leaving Discord, and a
+/// `universal_url` lets members on other platforms find the track.
+pub async fn reply_track_added(
+    ctx: &Context,
+    msg: &Message,
+    track: &TrackInfo,
+    colour: Option<u32>,
+    preview: Option<Vec<u8>>,
+    universal_url: Option<String>,
+) {
+    let mut description =
+        format!("{}\nAdded to the playlist", track.artists.join(", "));
+    if track.linked_from_uri.is_some() {
+        description.push_str(", as the version playable in the bot's market");
+    }
+    if let Some(universal_url) = universal_url {
+        description.push_str(&format!(
+            "\n[Listen on other platforms]({universal_url})"
+        ));
+    }
+    let result = msg
+        .channel_id
+        .send_message(&ctx.http, |message| {
+            if let Some(preview) = preview {
+                message.add_file(AttachmentType::Bytes {
+                    data: Cow::Owned(preview),
+                    filename: "preview.mp3".to_string(),
+                });
+            }
+            message.reference_message(msg).embed(|embed| {
+                embed
+                    .title(&track.name)
+                    .url(track.url())
+                    .description(description);
+                if let Some(image) = &track.album_image_url {
+                    embed.thumbnail(image);
+                }
+                if let Some(colour) = colour {
+                    embed.colour(colour);
+                }
+                embed
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        error!("Could not send feedback: {:?}", why);
+    }
+}
+
+/// Replies with an embed summarising an action taken on several things at
+/// once, with a line per thing in `details`.
+pub async fn reply_summary(
+    ctx: &Context,
+    msg: &Message,
+    summary: impl Display,
+    details: impl Display,
+) {
+    let result = msg
+        .channel_id
+        .send_message(&ctx.http, |message| {
+            message
+                .reference_message(msg)
+                .embed(|embed| embed.title(summary).description(details))
+        })
+        .await;
+    if let Err(why) = result {
+        error!("Could not send feedback: {:?}", why);
+    }
+}
+
+/// Replies to the message that triggered a failed action. When
+/// `ERROR_FEEDBACK_DELETE_SECS` is set, the reply is deleted again after that
+/// many seconds to keep the channel readable.
+pub async fn reply_error(ctx: &Context, msg: &Message, content: impl Display) {
+    let reply = match msg.reply(&ctx.http, content).await {
+        Ok(reply) => reply,
+        Err(why) => {
+            error!("Could not send feedback: {:?}", why);
+            return;
+        }
+    };
+
+    let delete_after = env_or("ERROR_FEEDBACK_DELETE_SECS", 0);
+    if delete_after > 0 {
+        let http = ctx.http.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(delete_after)).await;
+            if let Err(why) = reply.delete(&http).await {
+                error!("Could not delete feedback: {:?}", why);
+            }
+        });
+    }
+}
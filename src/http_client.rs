@@ -0,0 +1,68 @@
+use std::env;
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+use reqwest::Proxy;
+use url::Url;
+
+use crate::config::env_or;
+use crate::secrets;
+
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 8;
+
+/// Builds the HTTP client shared by everything that talks to the outside
+/// world. The client pools connections internally, so build it once and clone
+/// it rather than calling this per component.
+///
+/// Configured through the environment:
+/// - `HTTP_CONNECT_TIMEOUT_SECS`, `HTTP_REQUEST_TIMEOUT_SECS`
+/// - `HTTP_POOL_MAX_IDLE_PER_HOST`, `HTTP_POOL_IDLE_TIMEOUT_SECS`
+/// - `HTTP2_PRIOR_KNOWLEDGE`, set to `true` to speak HTTP/2 without
+///   negotiating it first
+/// - `HTTP_PROXY_URL`, an `http://`, `https://` or `socks5://` proxy that all
+///   requests are sent through, with optional `HTTP_PROXY_USERNAME` and
+///   `HTTP_PROXY_PASSWORD` credentials
+pub fn build() -> Client {
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(env_or(
+            "HTTP_CONNECT_TIMEOUT_SECS",
+            DEFAULT_CONNECT_TIMEOUT_SECS,
+        )))
+        .timeout(Duration::from_secs(env_or(
+            "HTTP_REQUEST_TIMEOUT_SECS",
+            DEFAULT_REQUEST_TIMEOUT_SECS,
+        )))
+        .pool_max_idle_per_host(env_or(
+            "HTTP_POOL_MAX_IDLE_PER_HOST",
+            DEFAULT_POOL_MAX_IDLE_PER_HOST,
+        ))
+        .pool_idle_timeout(Duration::from_secs(env_or(
+            "HTTP_POOL_IDLE_TIMEOUT_SECS",
+            DEFAULT_POOL_IDLE_TIMEOUT_SECS,
+        )));
+    if env_or("HTTP2_PRIOR_KNOWLEDGE", false) {
+        builder = builder.http2_prior_knowledge();
+    }
+    if let Some(proxy) = proxy_from_env() {
+        builder = builder.proxy(proxy);
+    }
+    builder.build().expect("Could not build the HTTP client")
+}
+
+fn proxy_from_env() -> Option<Proxy> {
+    let mut proxy_url = Url::parse(&env::var("HTTP_PROXY_URL").ok()?)
+        .expect("HTTP_PROXY_URL is not a valid URL");
+    // Credentials go in the URL rather than a Proxy-Authorization header so
+    // they also reach SOCKS proxies
+    if let Ok(username) = env::var("HTTP_PROXY_USERNAME") {
+        let password = secrets::read("HTTP_PROXY_PASSWORD");
+        proxy_url
+            .set_username(&username)
+            .and_then(|_| proxy_url.set_password(password.as_deref()))
+            .expect("HTTP_PROXY_URL cannot carry credentials");
+    }
+    Some(Proxy::all(proxy_url).expect("HTTP_PROXY_URL is not a valid proxy"))
+}
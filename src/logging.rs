@@ -0,0 +1,59 @@
+use std::env;
+
+use flexi_logger::{
+    opt_format, Age, Cleanup, Criterion, Duplicate, FileSpec, Logger,
+    LoggerHandle, Naming,
+};
+
+use crate::config::env_or;
+
+const DEFAULT_LOG_SPEC: &str = "info";
+const DEFAULT_MAX_LOG_SIZE_MB: u64 = 10;
+const DEFAULT_LOG_RETENTION: usize = 7;
+
+/// Starts logging to stderr, filtered by `RUST_LOG` (default `info`).
+///
+/// Setting `LOG_DIR` additionally writes `sonic_*.log` files there, rotated
+/// by `LOG_ROTATION`: `daily` (the default), `hourly`, or `size` to rotate
+/// once a file reaches `LOG_MAX_SIZE_MB`. Only the newest `LOG_RETENTION`
+/// rotated files are kept.
+///
+/// Logging stops when the returned handle is dropped, so keep it for the
+/// lifetime of the program.
+pub fn init() -> LoggerHandle {
+    let logger = Logger::try_with_env_or_str(DEFAULT_LOG_SPEC)
+        .expect("RUST_LOG is not a valid log specification")
+        .format(opt_format);
+    let logger = match env::var("LOG_DIR") {
+        Ok(directory) => logger
+            .log_to_file(
+                FileSpec::default().directory(directory).basename("sonic"),
+            )
+            .rotate(
+                rotation_criterion(),
+                Naming::Timestamps,
+                Cleanup::KeepLogFiles(env_or(
+                    "LOG_RETENTION",
+                    DEFAULT_LOG_RETENTION,
+                )),
+            )
+            .duplicate_to_stderr(Duplicate::All),
+        Err(_) => logger.log_to_stderr(),
+    };
+    logger.start().expect("Could not start the logger")
+}
+
+/// The log filter given at startup, from `RUST_LOG`.
+pub fn startup_spec() -> String {
+    env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_SPEC.to_string())
+}
+
+fn rotation_criterion() -> Criterion {
+    match env::var("LOG_ROTATION").as_deref() {
+        Ok("hourly") => Criterion::Age(Age::Hour),
+        Ok("size") => Criterion::Size(
+            env_or("LOG_MAX_SIZE_MB", DEFAULT_MAX_LOG_SIZE_MB) * 1024 * 1024,
+        ),
+        _ => Criterion::Age(Age::Day),
+    }
+}
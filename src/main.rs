@@ -1,7 +1,87 @@
+use std::env;
+use std::process;
+
+use log::{error, info};
+
+mod album_art;
+mod blocking;
+mod blocklist;
+mod chat_bridge;
+mod config;
 mod discord_client;
+mod external_links;
+mod feedback;
+mod http_client;
+mod logging;
+mod matrix;
+mod metrics;
+mod moderation;
+mod permissions;
+mod playlist_locks;
+mod playlist_manager;
+mod preflight;
+mod progress;
+mod quiz;
+mod quota;
+mod rate_limit;
+mod retry;
+mod secrets;
+mod selftest;
+mod single_flight;
+mod social;
+mod songlink;
 mod spotify_client;
+mod systemd;
+mod telegram;
+mod ttl_cache;
+mod twitch;
 
 #[tokio::main]
 async fn main() {
-    discord_client::start_bot().await;
+    let profile = config::load_profile();
+    let logger = logging::init();
+    match profile {
+        Ok(Some(profile)) => info!("Using the {} profile", profile),
+        Ok(None) => {}
+        Err(why) => {
+            error!("{}", why);
+            logger.flush();
+            process::exit(1);
+        }
+    }
+    if config::dry_run() {
+        info!("Dry run, changes to Spotify are logged and not made");
+    }
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("doctor") {
+        let report = selftest::run().await;
+        if args.iter().any(|arg| arg == "--json") {
+            println!("{}", report.to_json());
+        } else {
+            report.log();
+        }
+        // Exiting skips destructors, so the handle won't flush on its own
+        logger.flush();
+        process::exit(if report.passed() { 0 } else { 1 });
+    }
+    if config::env_or("SELFTEST_ON_STARTUP", false) {
+        let report = selftest::run().await;
+        report.log();
+        if !report.passed() {
+            logger.flush();
+            process::exit(1);
+        }
+    } else {
+        let validation = config::validate();
+        validation.log();
+        if validation.has_errors() {
+            logger.flush();
+            process::exit(1);
+        }
+    }
+    if config::discord_enabled() {
+        discord_client::start_bot(logger.clone()).await;
+    } else {
+        chat_bridge::run_without_discord().await;
+    }
 }
@@ -0,0 +1,243 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{error, info};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::chat_bridge::{ChatMessage, ChatSource};
+use crate::config::env_or;
+use crate::secrets;
+
+// The homeserver holds a sync open until an event arrives or this many
+// seconds pass
+const LONG_POLL_SECS: u64 = 25;
+
+/// Matrix rooms the bot's account has joined, read by long polling the
+/// Client-Server API's `/sync`.
+pub struct MatrixChat {
+    http_client: Client,
+    homeserver_url: Url,
+    access_token: String,
+    /// The room ID or alias in `MATRIX_ROOM_ID`, joined on connecting.
+    room: Option<String>,
+    /// Once connected, the bot's own user ID and the room it reads, if only
+    /// one.
+    session: Option<(String, Option<String>)>,
+    /// Where the last sync left off. Messages sent before the first sync
+    /// are skipped rather than added again on every restart.
+    since: Option<String>,
+    transaction: u64,
+}
+
+impl MatrixChat {
+    /// `None` unless `MATRIX_HOMESERVER_URL` and a `MATRIX_ACCESS_TOKEN` are
+    /// set. `MATRIX_ROOM_ID` keeps the bot to one room, which it joins.
+    pub fn from_env(http_client: Client) -> Option<MatrixChat> {
+        let homeserver_url = env_or("MATRIX_HOMESERVER_URL", String::new());
+        if homeserver_url.is_empty() {
+            return None;
+        }
+        let homeserver_url = match Url::parse(&homeserver_url) {
+            Ok(url) => url,
+            Err(why) => {
+                error!("MATRIX_HOMESERVER_URL is not a URL: {}", why);
+                return None;
+            }
+        };
+        // Without a scheme, `host:port` parses as a URL with no path to
+        // build endpoints on
+        if !["http", "https"].contains(&homeserver_url.scheme()) {
+            error!(
+                "MATRIX_HOMESERVER_URL must start with https:// or http://, \
+                 not {}",
+                homeserver_url
+            );
+            return None;
+        }
+        let access_token = secrets::read("MATRIX_ACCESS_TOKEN")?;
+        let room = Some(env_or("MATRIX_ROOM_ID", String::new()))
+            .filter(|room| !room.is_empty());
+        Some(MatrixChat {
+            http_client,
+            homeserver_url,
+            access_token,
+            room,
+            session: None,
+            since: None,
+            transaction: 0,
+        })
+    }
+
+    /// The Client-Server API endpoint made of `segments`, each escaped as
+    /// room IDs and aliases need.
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.homeserver_url.clone();
+        url.path_segments_mut()
+            .expect("the homeserver URL is an http(s) URL")
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"])
+            .extend(segments);
+        url
+    }
+
+    fn send(
+        &self,
+        request: RequestBuilder,
+        timeout: Duration,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let response = request
+            .bearer_auth(&self.access_token)
+            .timeout(timeout)
+            .send()?;
+        let status = response.status();
+        let body: Value = response.json()?;
+        if !status.is_success() {
+            let error = body["error"].as_str().unwrap_or("no description");
+            return Err(format!("Matrix returned {status}: {error}").into());
+        }
+        Ok(body)
+    }
+
+    fn connect(
+        &mut self,
+    ) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+        let timeout = Duration::from_secs(LONG_POLL_SECS);
+        let whoami = self.send(
+            self.http_client.get(self.endpoint(&["account", "whoami"])),
+            timeout,
+        )?;
+        let user_id = whoami["user_id"]
+            .as_str()
+            .ok_or("Matrix did not say who the access token belongs to")?
+            .to_string();
+        let room_id = match &self.room {
+            Some(room) => {
+                let joined = self.send(
+                    self.http_client
+                        .post(self.endpoint(&["join", room]))
+                        .json(&json!({})),
+                    timeout,
+                )?;
+                let room_id = joined["room_id"]
+                    .as_str()
+                    .ok_or("Matrix did not say which room was joined")?;
+                Some(room_id.to_string())
+            }
+            None => None,
+        };
+        info!("Watching Matrix as {}", user_id);
+        Ok((user_id, room_id))
+    }
+
+    fn sync(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        // Only messages are wanted, not presence, typing or room state
+        let nothing = json!({ "types": [] });
+        let filter = json!({
+            "presence": nothing,
+            "account_data": nothing,
+            "room": {
+                "state": nothing,
+                "ephemeral": nothing,
+                "account_data": nothing,
+                "timeline": { "types": ["m.room.message"] },
+            },
+        });
+        let mut query = vec![("filter", filter.to_string())];
+        match &self.since {
+            Some(since) => {
+                query.push(("since", since.clone()));
+                query.push(("timeout", (LONG_POLL_SECS * 1000).to_string()));
+            }
+            None => query.push(("timeout", "0".to_string())),
+        }
+        self.send(
+            self.http_client.get(self.endpoint(&["sync"])).query(&query),
+            Duration::from_secs(LONG_POLL_SECS + 10),
+        )
+    }
+}
+
+impl ChatSource for MatrixChat {
+    fn name(&self) -> &'static str {
+        "Matrix"
+    }
+
+    fn receive(
+        &mut self,
+    ) -> Result<Vec<ChatMessage>, Box<dyn std::error::Error>> {
+        if self.session.is_none() {
+            self.session = Some(self.connect()?);
+        }
+        let response = self.sync()?;
+        let first_sync = self.since.is_none();
+        self.since = response["next_batch"].as_str().map(String::from);
+        let Some((user_id, room_id)) = &self.session else {
+            return Ok(Vec::new());
+        };
+        if first_sync {
+            return Ok(Vec::new());
+        }
+        let mut messages = Vec::new();
+        let rooms = response["rooms"]["join"].as_object().into_iter().flatten();
+        for (room, joined) in rooms {
+            if room_id.as_ref().is_some_and(|wanted| wanted != room) {
+                continue;
+            }
+            let events = joined["timeline"]["events"].as_array();
+            for event in events.into_iter().flatten() {
+                let content = &event["content"];
+                let (Some(sender), Some(event_id), Some(text)) = (
+                    event["sender"].as_str(),
+                    event["event_id"].as_str(),
+                    content["body"].as_str(),
+                ) else {
+                    continue;
+                };
+                if sender == user_id || content["msgtype"] != "m.text" {
+                    continue;
+                }
+                messages.push(ChatMessage {
+                    author: sender.to_string(),
+                    text: text.to_string(),
+                    chat_id: room.clone(),
+                    message_id: event_id.to_string(),
+                });
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Replies as a notice, which other bots know not to answer.
+    fn reply(
+        &mut self,
+        message: &ChatMessage,
+        content: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Transaction IDs only have to be unique for this access token
+        self.transaction += 1;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let transaction_id = format!("sonic-{now}-{}", self.transaction);
+        let url = self.endpoint(&[
+            "rooms",
+            &message.chat_id,
+            "send",
+            "m.room.message",
+            &transaction_id,
+        ]);
+        self.send(
+            self.http_client.put(url).json(&json!({
+                "msgtype": "m.notice",
+                "body": content,
+                "m.relates_to": {
+                    "m.in_reply_to": { "event_id": message.message_id },
+                },
+            })),
+            Duration::from_secs(LONG_POLL_SECS),
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use url::Url;
+
+// Upper bounds of the latency histogram buckets, plus one for anything slower
+const LATENCY_BUCKETS_MS: [u64; 6] = [100, 250, 500, 1000, 2500, 5000];
+// Path segments followed by an ID, such as `/playlists/{id}/tracks`
+const ID_ROUTES: [&str; 5] =
+    ["albums", "artists", "playlists", "tracks", "users"];
+
+/// Request counts, failures and a latency histogram for one endpoint or
+/// command.
+#[derive(Clone, Debug, Default)]
+pub struct EndpointStats {
+    pub requests: u64,
+    pub errors: u64,
+    latency_buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+}
+
+impl EndpointStats {
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            return 0.0;
+        }
+        self.errors as f64 / self.requests as f64
+    }
+
+    /// The bucket bound that at least `percentile` percent of requests
+    /// finished within, or `None` if they took longer than the largest bound.
+    pub fn latency_percentile_ms(&self, percentile: f64) -> Option<u64> {
+        let wanted = (self.requests as f64 * percentile / 100.0).ceil() as u64;
+        let mut seen = 0;
+        for (bound, count) in
+            LATENCY_BUCKETS_MS.iter().zip(self.latency_buckets)
+        {
+            seen += count;
+            if seen >= wanted {
+                return Some(*bound);
+            }
+        }
+        None
+    }
+}
+
+/// Per-endpoint request statistics since startup, or per-command run
+/// statistics. Clones share the same statistics.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    endpoints: Arc<Mutex<BTreeMap<String, EndpointStats>>>,
+}
+
+impl Metrics {
+    pub fn record(&self, endpoint: &str, latency: Duration, failed: bool) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints.entry(endpoint.to_string()).or_default();
+        stats.requests += 1;
+        if failed {
+            stats.errors += 1;
+        }
+        let latency_ms = latency.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| latency_ms <= bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        stats.latency_buckets[bucket] += 1;
+    }
+
+    /// The statistics of every endpoint requested so far, by endpoint.
+    pub fn endpoints(&self) -> BTreeMap<String, EndpointStats> {
+        self.endpoints.lock().unwrap().clone()
+    }
+}
+
+/// Slash command runs since startup, and how often each error was shown.
+#[derive(Default)]
+pub struct CommandMetrics {
+    pub runs: Metrics,
+    errors: Mutex<HashMap<String, u64>>,
+}
+
+impl CommandMetrics {
+    pub fn record(
+        &self,
+        command: &str,
+        latency: Duration,
+        result: &Result<String, String>,
+    ) {
+        self.runs.record(command, latency, result.is_err());
+        if let Err(why) = result {
+            let mut errors = self.errors.lock().unwrap();
+            *errors.entry(format!("{command}: {why}")).or_default() += 1;
+        }
+    }
+
+    /// The `count` most frequent errors, most frequent first.
+    pub fn top_errors(&self, count: usize) -> Vec<(String, u64)> {
+        let mut errors: Vec<(String, u64)> =
+            self.errors.lock().unwrap().clone().into_iter().collect();
+        errors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        errors.truncate(count);
+        errors
+    }
+}
+
+/// Reduces a request URL to its path with the segment after each of
+/// `ID_ROUTES` replaced by `{id}`, so requests for different tracks, playlists
+/// or users count towards one endpoint. Going by position rather than by what
+/// an ID looks like also catches user IDs, which are free-form.
+pub fn endpoint_template(url: &Url) -> String {
+    let mut previous = "";
+    url.path()
+        .split('/')
+        .map(|segment| {
+            let is_id = ID_ROUTES.contains(&previous) && !segment.is_empty();
+            previous = segment;
+            if is_id {
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(path: &str) -> String {
+        let url = format!("https://api.spotify.com{path}");
+        endpoint_template(&Url::parse(&url).unwrap())
+    }
+
+    #[test]
+    fn replaces_ids_by_route_position() {
+        assert_eq!(
+            template("/v1/playlists/3nf65T5wXvLYLvT6xvXoLf/tracks?offset=100"),
+            "/v1/playlists/{id}/tracks"
+        );
+        assert_eq!(
+            template("/v1/users/some.user_42/playlists"),
+            "/v1/users/{id}/playlists"
+        );
+        assert_eq!(
+            template("/v1/tracks/4uLU6hMCjMI75M1A2tKUQC"),
+            "/v1/tracks/{id}"
+        );
+    }
+
+    #[test]
+    fn leaves_routes_without_ids_alone() {
+        assert_eq!(template("/v1/artists?ids=a,b"), "/v1/artists");
+        assert_eq!(template("/v1/me/tracks"), "/v1/me/tracks");
+        assert_eq!(template("/v1/me/player/queue"), "/v1/me/player/queue");
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serenity::model::id::UserId;
+
+use crate::config::env_or;
+
+/// A track linked in a message, with the artists it credits if it could be
+/// looked up.
+pub struct Link {
+    pub track_id: String,
+    pub artists: Vec<String>,
+}
+
+/// Why a member's links were taken for spam, and how long the rest of theirs
+/// will be ignored.
+pub struct Flagged {
+    pub reason: String,
+    pub muted_for: Duration,
+}
+
+#[derive(Default)]
+struct Activity {
+    /// When each message with links was posted, within the window.
+    messages: VecDeque<Instant>,
+    links: VecDeque<(Instant, Link)>,
+    muted_until: Option<Instant>,
+}
+
+/// Spots members flooding the playlist and quietly ignores their links for a
+/// while. Within `SPAM_WINDOW_SECS`, a member is flagged for linking
+/// `SPAM_SAME_ARTIST_LIMIT` tracks by one artist, the same track
+/// `SPAM_SAME_TRACK_LIMIT` times, or posting `SPAM_MESSAGE_LIMIT` messages
+/// with links. A limit of 0 turns that check off. Flagged members are muted
+/// for `SPAM_MUTE_SECS`, 0 only flags them. Members are Discord users unless
+/// `K` says otherwise.
+pub struct Moderation<K = UserId> {
+    activity: Mutex<HashMap<K, Activity>>,
+}
+
+impl<K> Default for Moderation<K> {
+    fn default() -> Self {
+        Moderation {
+            activity: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash> Moderation<K> {
+    pub fn is_muted(&self, user_id: &K) -> bool {
+        let activity = self.activity.lock().unwrap();
+        activity
+            .get(user_id)
+            .and_then(|activity| activity.muted_until)
+            .is_some_and(|muted_until| Instant::now() < muted_until)
+    }
+
+    /// Records the links of a message, and mutes the member if they now look
+    /// like spam.
+    pub fn screen(&self, user_id: K, links: Vec<Link>) -> Option<Flagged> {
+        let window = Duration::from_secs(env_or("SPAM_WINDOW_SECS", 60));
+        let now = Instant::now();
+        let mut activity = self.activity.lock().unwrap();
+        let activity = activity.entry(user_id).or_default();
+        while activity
+            .messages
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= window)
+        {
+            activity.messages.pop_front();
+        }
+        while activity
+            .links
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) >= window)
+        {
+            activity.links.pop_front();
+        }
+        activity.messages.push_back(now);
+        activity
+            .links
+            .extend(links.into_iter().map(|link| (now, link)));
+
+        let reason = spam_reason(activity, window)?;
+        let muted_for = Duration::from_secs(env_or("SPAM_MUTE_SECS", 900));
+        if !muted_for.is_zero() {
+            activity.muted_until = Some(now + muted_for);
+        }
+        // Start over, so the same burst isn't flagged again after the mute
+        activity.messages.clear();
+        activity.links.clear();
+        Some(Flagged { reason, muted_for })
+    }
+}
+
+fn spam_reason(activity: &Activity, window: Duration) -> Option<String> {
+    let secs = window.as_secs();
+    let message_limit = env_or("SPAM_MESSAGE_LIMIT", 15usize);
+    if message_limit > 0 && activity.messages.len() >= message_limit {
+        return Some(format!(
+            "Posted {} messages with links in {secs} seconds",
+            activity.messages.len()
+        ));
+    }
+    let track_limit = env_or("SPAM_SAME_TRACK_LIMIT", 3usize);
+    let (track_id, count) = most_common(
+        activity
+            .links
+            .iter()
+            .map(|(_, link)| link.track_id.as_str()),
+    )?;
+    if track_limit > 0 && count >= track_limit {
+        return Some(format!(
+            "Linked track `{track_id}` {count} times in {secs} seconds"
+        ));
+    }
+    let artist_limit = env_or("SPAM_SAME_ARTIST_LIMIT", 10usize);
+    let (artist, count) = most_common(
+        activity
+            .links
+            .iter()
+            .flat_map(|(_, link)| &link.artists)
+            .map(String::as_str),
+    )?;
+    if artist_limit > 0 && count >= artist_limit {
+        return Some(format!(
+            "Linked {count} tracks by {artist} in {secs} seconds"
+        ));
+    }
+    None
+}
+
+fn most_common<'a>(
+    values: impl Iterator<Item = &'a str>,
+) -> Option<(&'a str, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    counts.into_iter().max_by_key(|(_, count)| *count)
+}
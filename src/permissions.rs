@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use serenity::model::guild::Member;
+use serenity::model::id::RoleId;
+use serenity::prelude::Mentionable;
+
+/// Who may run a command.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Requirement {
+    Anyone,
+    ManageGuild,
+    /// Members with the role. Those who can manage the server always may,
+    /// so a deleted role can't lock them out.
+    Role(RoleId),
+}
+
+impl Requirement {
+    pub fn allows(self, member: Option<&Member>) -> bool {
+        let manages_guild = member
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| permissions.manage_guild());
+        match self {
+            Requirement::Anyone => true,
+            Requirement::ManageGuild => manages_guild,
+            Requirement::Role(role_id) => {
+                manages_guild
+                    || member
+                        .is_some_and(|member| member.roles.contains(&role_id))
+            }
+        }
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Requirement::Anyone => "everyone".to_string(),
+            Requirement::ManageGuild => {
+                "members who can manage the server".to_string()
+            }
+            Requirement::Role(role_id) => {
+                format!("members with {}", role_id.mention())
+            }
+        }
+    }
+}
+
+/// Every slash command, with who may run it unless a server says otherwise.
+/// Commands are registered with Discord open to everyone, so a role given
+/// through `/admin permissions` isn't hidden behind Discord's own defaults;
+/// the bot checks these itself before running anything.
+pub const COMMANDS: &[(&str, Requirement)] = &[
+    ("admin", Requirement::ManageGuild),
+    ("discover", Requirement::Anyone),
+    ("event", Requirement::ManageGuild),
+    ("genres", Requirement::Anyone),
+    ("queue", Requirement::Anyone),
+    ("quiz", Requirement::Anyone),
+    ("session", Requirement::Anyone),
+    ("status", Requirement::ManageGuild),
+];
+
+/// The roles a server requires for some of its commands. Every check that a
+/// member may run a command goes through here, including the buttons that
+/// act on behalf of `/admin`.
+#[derive(Clone, Default)]
+pub struct CommandPermissions {
+    roles: BTreeMap<String, RoleId>,
+}
+
+impl CommandPermissions {
+    pub fn requirement(&self, command: &str) -> Requirement {
+        if let Some(&role_id) = self.roles.get(command) {
+            return Requirement::Role(role_id);
+        }
+        COMMANDS
+            .iter()
+            .find(|(name, _)| *name == command)
+            .map(|(_, requirement)| *requirement)
+            .unwrap_or(Requirement::ManageGuild)
+    }
+
+    pub fn require_role(&mut self, command: &str, role_id: RoleId) {
+        self.roles.insert(command.to_string(), role_id);
+    }
+
+    /// Returns whether the command required a role.
+    pub fn reset(&mut self, command: &str) -> bool {
+        self.roles.remove(command).is_some()
+    }
+
+    /// One line per command saying who may run it.
+    pub fn describe(&self) -> String {
+        COMMANDS
+            .iter()
+            .map(|(command, _)| {
+                let note = if self.roles.contains_key(*command) {
+                    ""
+                } else {
+                    " (default)"
+                };
+                format!(
+                    "**/{command}**: {}{note}",
+                    self.requirement(command).describe()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// One lock per playlist, held across a read-modify-write of its tracks so
+/// two commands rewriting the same playlist take turns instead of
+/// interleaving. Only serializes within this process.
+#[derive(Clone, Default)]
+pub struct PlaylistLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+}
+
+impl PlaylistLocks {
+    /// Waits for any other change to the playlist to finish. The playlist is
+    /// unlocked when the guard is dropped.
+    pub async fn lock(&self, playlist_id: &str) -> OwnedMutexGuard<()> {
+        let lock = self
+            .locks
+            .lock()
+            .await
+            .entry(playlist_id.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+}
@@ -0,0 +1,825 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+use log::{error, warn};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+use crate::blocklist::Blocklist;
+use crate::spotify_client::{
+    PlaylistItem, SpotifyClient, SpotifyError, TrackInfo,
+};
+
+/// Why a playlist item was considered a copy of an earlier one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuplicateKind {
+    /// The same Spotify track URI.
+    Exact,
+    /// A different release of the same recording, matched by ISRC.
+    Isrc,
+    /// The same title and lead artist once versions and features are ignored.
+    Fuzzy,
+}
+
+impl DuplicateKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            DuplicateKind::Exact => "same track",
+            DuplicateKind::Isrc => "same recording",
+            DuplicateKind::Fuzzy => "same title and artist",
+        }
+    }
+}
+
+pub struct Duplicate {
+    pub position: usize,
+    pub track: TrackInfo,
+    pub original: TrackInfo,
+    pub kind: DuplicateKind,
+}
+
+/// Finds every playlist item that repeats an earlier one. The first
+/// occurrence of a track is always kept, so only later copies are returned.
+/// Items are consumed as they arrive, so only tracks seen for the first time
+/// are held in memory.
+pub fn find_duplicates<E>(
+    items: impl Iterator<Item = Result<PlaylistItem, E>>,
+) -> Result<Vec<Duplicate>, E> {
+    let mut originals: Vec<TrackInfo> = Vec::new();
+    let mut by_uri: HashMap<String, usize> = HashMap::new();
+    let mut by_isrc: HashMap<String, usize> = HashMap::new();
+    let mut by_title: HashMap<String, usize> = HashMap::new();
+    let mut duplicates = Vec::new();
+
+    for (position, item) in items.enumerate() {
+        let track = match item?.track {
+            Some(track) => track,
+            None => continue,
+        };
+        let title_key = fuzzy_key(&track);
+
+        let original = by_uri
+            .get(&track.uri)
+            .map(|original| (*original, DuplicateKind::Exact))
+            .or_else(|| {
+                by_isrc
+                    .get(track.isrc.as_ref()?)
+                    .map(|original| (*original, DuplicateKind::Isrc))
+            })
+            .or_else(|| {
+                by_title
+                    .get(title_key.as_ref()?)
+                    .map(|original| (*original, DuplicateKind::Fuzzy))
+            });
+
+        match original {
+            Some((original, kind)) => duplicates.push(Duplicate {
+                position,
+                track,
+                original: originals[original].clone(),
+                kind,
+            }),
+            None => {
+                let index = originals.len();
+                by_uri.insert(track.uri.clone(), index);
+                if let Some(isrc) = &track.isrc {
+                    by_isrc.insert(isrc.clone(), index);
+                }
+                if let Some(title_key) = title_key {
+                    by_title.insert(title_key, index);
+                }
+                originals.push(track);
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+/// Reduces a track to its normalized title and lead artist.
+fn fuzzy_key(track: &TrackInfo) -> Option<String> {
+    let artist = track.artists.first()?;
+    let mut key = normalize_title(&track.name);
+    if key.is_empty() {
+        return None;
+    }
+    key.push('|');
+    key.push_str(&normalize(artist));
+    Some(key)
+}
+
+/// Lowercases a title and drops anything in brackets and anything after
+/// " - ", which is where Spotify puts version suffixes such as "(feat. ...)"
+/// or "- Remastered 2011".
+pub fn normalize_title(title: &str) -> String {
+    let title = title.split(" - ").next().unwrap_or_default();
+
+    let mut depth: usize = 0;
+    let mut stripped = String::new();
+    for c in title.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+    normalize(&stripped)
+}
+
+/// Keeps only the lowercased letters and digits of `text`.
+pub fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Problems found by a full scan of a playlist. Tracks are paired with their
+/// playlist positions.
+pub struct HealthReport {
+    /// Positions of items whose track Spotify no longer serves at all.
+    pub missing: Vec<usize>,
+    /// Positions of local files, which only play on the adder's device.
+    pub local: Vec<usize>,
+    /// Tracks that can't be played in the configured market.
+    pub unplayable: Vec<(usize, TrackInfo)>,
+    pub duplicates: Vec<Duplicate>,
+    pub unpopular: Vec<(usize, TrackInfo)>,
+    /// Tracks longer than the duration the report was asked to flag.
+    pub overlong: Vec<(usize, TrackInfo)>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.missing.is_empty()
+            && self.local.is_empty()
+            && self.unplayable.is_empty()
+            && self.duplicates.is_empty()
+            && self.unpopular.is_empty()
+            && self.overlong.is_empty()
+    }
+}
+
+/// Checks every item of a playlist for problems worth fixing.
+pub fn health_report(
+    items: &[PlaylistItem],
+    max_duration_ms: u64,
+) -> HealthReport {
+    let mut report = HealthReport {
+        missing: Vec::new(),
+        local: Vec::new(),
+        unplayable: Vec::new(),
+        duplicates: Vec::new(),
+        unpopular: Vec::new(),
+        overlong: Vec::new(),
+    };
+    for (position, item) in items.iter().enumerate() {
+        // Local files have no catalogue data to check
+        if item.is_local {
+            report.local.push(position);
+            continue;
+        }
+        let track = match &item.track {
+            Some(track) => track,
+            None => {
+                report.missing.push(position);
+                continue;
+            }
+        };
+        if track.is_playable == Some(false) {
+            report.unplayable.push((position, track.clone()));
+        }
+        if track.popularity == 0 {
+            report.unpopular.push((position, track.clone()));
+        }
+        if track.duration_ms > max_duration_ms {
+            report.overlong.push((position, track.clone()));
+        }
+    }
+    report.duplicates =
+        match find_duplicates(items.iter().cloned().map(Ok::<_, Infallible>)) {
+            Ok(duplicates) => duplicates,
+            Err(never) => match never {},
+        };
+    report
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    /// Oldest additions first.
+    DateAdded,
+    /// Alphabetically by lead artist, then title.
+    Artist,
+    /// Most popular first.
+    Popularity,
+    /// Shortest first.
+    Duration,
+}
+
+impl SortKey {
+    pub fn from_name(name: &str) -> Option<SortKey> {
+        match name {
+            "date_added" => Some(SortKey::DateAdded),
+            "artist" => Some(SortKey::Artist),
+            "popularity" => Some(SortKey::Popularity),
+            "duration" => Some(SortKey::Duration),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, a: &PlaylistItem, b: &PlaylistItem) -> Ordering {
+        // Unavailable tracks always sink to the end
+        let (a_track, b_track) = match (&a.track, &b.track) {
+            (Some(a_track), Some(b_track)) => (a_track, b_track),
+            (a_track, b_track) => {
+                return b_track.is_some().cmp(&a_track.is_some())
+            }
+        };
+        match self {
+            SortKey::DateAdded => a.added_at.cmp(&b.added_at),
+            SortKey::Artist => {
+                artist_sort_key(a_track).cmp(&artist_sort_key(b_track))
+            }
+            SortKey::Popularity => {
+                Reverse(a_track.popularity).cmp(&Reverse(b_track.popularity))
+            }
+            SortKey::Duration => a_track.duration_ms.cmp(&b_track.duration_ms),
+        }
+    }
+}
+
+fn artist_sort_key(track: &TrackInfo) -> (String, String) {
+    let artist = track.artists.first().cloned().unwrap_or_default();
+    (artist.to_lowercase(), track.name.to_lowercase())
+}
+
+/// A single Spotify reorder request: move `range_length` items starting at
+/// `range_start` so they sit just before `insert_before`.
+#[derive(Debug, PartialEq)]
+pub struct RangeMove {
+    pub range_start: usize,
+    pub range_length: usize,
+    pub insert_before: usize,
+}
+
+/// Returns the moves that reorder `items` by `key`. Runs of items that are
+/// already in the right relative order are moved together, so a mostly
+/// sorted playlist needs only a few requests.
+pub fn plan_sort(items: &[PlaylistItem], key: SortKey) -> Vec<RangeMove> {
+    let mut target: Vec<usize> = (0..items.len()).collect();
+    target.sort_by(|a, b| key.compare(&items[*a], &items[*b]));
+
+    let mut current: Vec<usize> = (0..items.len()).collect();
+    let mut moves = Vec::new();
+    for position in 0..target.len() {
+        if current[position] == target[position] {
+            continue;
+        }
+        // Everything before `position` is already in place, so the wanted
+        // item is somewhere after it
+        let range_start = current
+            .iter()
+            .position(|item| *item == target[position])
+            .expect("sort target is a permutation of the playlist");
+        let mut range_length = 1;
+        while range_start + range_length < current.len()
+            && current[range_start + range_length]
+                == target[position + range_length]
+        {
+            range_length += 1;
+        }
+
+        let range: Vec<usize> = current
+            .drain(range_start..range_start + range_length)
+            .collect();
+        current.splice(position..position, range);
+        moves.push(RangeMove {
+            range_start,
+            range_length,
+            insert_before: position,
+        });
+    }
+    moves
+}
+
+/// The `count` artists credited on the most distinct tracks, as
+/// `(artist ID, name)` pairs, most frequent first.
+pub fn top_artists(
+    tracks: &[TrackInfo],
+    count: usize,
+) -> Vec<(String, String)> {
+    let mut artists: HashMap<&str, (&str, HashSet<&str>)> = HashMap::new();
+    for track in tracks {
+        for (id, name) in track.artist_ids.iter().zip(&track.artists) {
+            let (_, uris) = artists.entry(id).or_insert((name, HashSet::new()));
+            uris.insert(&track.uri);
+        }
+    }
+    let mut artists: Vec<(&str, &str, usize)> = artists
+        .into_iter()
+        .map(|(id, (name, uris))| (id, name, uris.len()))
+        .collect();
+    artists.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.1.cmp(b.1)));
+    artists
+        .into_iter()
+        .take(count)
+        .map(|(id, name, _)| (id.to_string(), name.to_string()))
+        .collect()
+}
+
+/// The share of `tracks` tagged with each genre, most common first. A track
+/// takes the genres of all its artists, so shares can add up to more than one.
+pub fn genre_breakdown(
+    tracks: &[TrackInfo],
+    artist_genres: &HashMap<String, Vec<String>>,
+) -> Vec<(String, f64)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for track in tracks {
+        let genres: HashSet<&str> = track
+            .artist_ids
+            .iter()
+            .filter_map(|id| artist_genres.get(id))
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        for genre in genres {
+            *counts.entry(genre).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    counts
+        .into_iter()
+        .map(|(genre, count)| {
+            (genre.to_string(), count as f64 / tracks.len() as f64)
+        })
+        .collect()
+}
+
+/// Tracks from Spotify's "This Is" playlists for the `artist_count` artists
+/// most common in `tracks`, leaving out any already in `tracks`.
+pub fn editorial_candidates(
+    client: &mut SpotifyClient,
+    tracks: &[TrackInfo],
+    artist_count: usize,
+) -> Result<Vec<TrackInfo>, Box<dyn std::error::Error>> {
+    let mut seen: HashSet<String> =
+        tracks.iter().map(|track| track.uri.clone()).collect();
+    let mut candidates = Vec::new();
+    for (_, artist_name) in top_artists(tracks, artist_count) {
+        let query = format!("This Is {artist_name}");
+        let Some(playlist_id) = client.search_editorial_playlist(&query)?
+        else {
+            continue;
+        };
+        for track in client.get_playlist_tracks(&playlist_id)? {
+            if seen.insert(track.uri.clone()) {
+                candidates.push(track);
+            }
+        }
+    }
+    Ok(candidates)
+}
+
+/// Picks items whose durations add up to as close to `target_ms` as
+/// possible, and at most `tolerance_ms` away from it. Returns indices into
+/// `durations_ms`, or `None` when no combination is close enough. Earlier
+/// items are preferred when several combinations are equally close.
+pub fn fit_duration(
+    durations_ms: &[u64],
+    target_ms: u64,
+    tolerance_ms: u64,
+) -> Option<Vec<usize>> {
+    // Subset sum over whole seconds keeps the table small
+    let seconds: Vec<usize> = durations_ms
+        .iter()
+        .map(|ms| ((ms + 500) / 1000) as usize)
+        .collect();
+    let target = ((target_ms + 500) / 1000) as usize;
+    let tolerance = (tolerance_ms / 1000) as usize;
+    let limit = target + tolerance;
+
+    // `last_item[sum]` is the item that first reached `sum`. Sums are filled
+    // from the top down, so the rest of that sum was reached by earlier
+    // items only and following the chain never reuses an item.
+    let mut last_item: Vec<Option<usize>> = vec![None; limit + 1];
+    let mut reachable = vec![false; limit + 1];
+    reachable[0] = true;
+    for (item, &duration) in seconds.iter().enumerate() {
+        if duration == 0 || duration > limit {
+            continue;
+        }
+        for sum in (duration..=limit).rev() {
+            if !reachable[sum] && reachable[sum - duration] {
+                reachable[sum] = true;
+                last_item[sum] = Some(item);
+            }
+        }
+    }
+
+    let best = (target.saturating_sub(tolerance)..=limit)
+        .filter(|&sum| sum > 0 && reachable[sum])
+        .min_by_key(|&sum| sum.abs_diff(target))?;
+    let mut picked = Vec::new();
+    let mut sum = best;
+    while let Some(item) = last_item[sum] {
+        picked.push(item);
+        sum -= seconds[item];
+    }
+    picked.reverse();
+    Some(picked)
+}
+
+/// Picks a random catalogue item whose track is not in `exclude`, favouring
+/// recent additions: the newest candidate is `n` times as likely to be picked
+/// as the oldest of `n` candidates.
+pub fn pick_weighted_by_recency<R: Rng>(
+    items: Vec<PlaylistItem>,
+    exclude: &HashSet<String>,
+    rng: &mut R,
+) -> Option<PlaylistItem> {
+    let mut candidates: Vec<PlaylistItem> = items
+        .into_iter()
+        .filter(|item| {
+            !item.is_local
+                && item
+                    .track
+                    .as_ref()
+                    .is_some_and(|track| !exclude.contains(&track.uri))
+        })
+        .collect();
+    // ISO 8601 timestamps sort chronologically as strings
+    candidates.sort_by(|a, b| a.added_at.cmp(&b.added_at));
+    let weights = WeightedIndex::new(1..=candidates.len()).ok()?;
+    let index = weights.sample(rng);
+    Some(candidates.swap_remove(index))
+}
+
+/// What became of a track linked in a message.
+pub enum AddOutcome {
+    Added(TrackInfo),
+    AlreadyInPlaylist(TrackInfo),
+    /// Region locked or withdrawn in the configured market, so it would show
+    /// up greyed out in the playlist.
+    Unplayable(TrackInfo),
+    /// By the blocked artist named in the second field.
+    Blocked(TrackInfo, String),
+}
+
+/// The track URIs of a playlist, kept so that checking a link for duplicates
+/// doesn't page through the whole playlist, along with how many tracks each
+/// artist is credited on. Both are reloaded whenever the playlist's snapshot
+/// ID shows it was changed elsewhere.
+pub struct PlaylistCache {
+    playlist_id: String,
+    state: Mutex<CachedPlaylist>,
+}
+
+#[derive(Default)]
+struct CachedPlaylist {
+    snapshot_id: Option<String>,
+    uris: HashSet<String>,
+    artist_track_counts: HashMap<String, usize>,
+    /// URIs being added right now, so a second link to the same track
+    /// counts as a duplicate instead of being added again.
+    pending: HashSet<String>,
+}
+
+impl CachedPlaylist {
+    fn insert(&mut self, track: &TrackInfo) {
+        if self.uris.insert(track.uri.clone()) {
+            for artist_id in &track.artist_ids {
+                *self
+                    .artist_track_counts
+                    .entry(artist_id.clone())
+                    .or_default() += 1;
+            }
+        }
+    }
+}
+
+impl PlaylistCache {
+    pub fn new(playlist_id: &str) -> PlaylistCache {
+        PlaylistCache {
+            playlist_id: playlist_id.to_string(),
+            state: Mutex::new(CachedPlaylist::default()),
+        }
+    }
+
+    /// Reloads the playlist unless it is unchanged since it was loaded,
+    /// which only costs a single request.
+    pub fn refresh(
+        &self,
+        client: &mut SpotifyClient,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let snapshot = client.get_playlist_snapshot(&self.playlist_id)?;
+        let snapshot_id = Some(snapshot.snapshot_id);
+        if self.state.lock().unwrap().snapshot_id == snapshot_id {
+            return Ok(());
+        }
+        let mut cached = CachedPlaylist {
+            snapshot_id,
+            ..CachedPlaylist::default()
+        };
+        for track in client.get_playlist_tracks(&self.playlist_id)? {
+            cached.insert(&track);
+        }
+        let mut state = self.state.lock().unwrap();
+        cached.pending = std::mem::take(&mut state.pending);
+        *state = cached;
+        Ok(())
+    }
+
+    pub fn contains(&self, uri: &str) -> bool {
+        self.state.lock().unwrap().uris.contains(uri)
+    }
+
+    /// Whether each of `uris` is in the playlist, refreshing the cache once
+    /// for all of them. Should the refresh fail, the playlist is read
+    /// directly instead.
+    pub fn contains_all(
+        &self,
+        client: &mut SpotifyClient,
+        uris: &[&str],
+    ) -> Vec<bool> {
+        let why = match self.refresh(client) {
+            Ok(()) => {
+                let state = self.state.lock().unwrap();
+                return uris
+                    .iter()
+                    .map(|uri| state.uris.contains(*uri))
+                    .collect();
+            }
+            Err(why) => why,
+        };
+        warn!("Could not refresh the playlist cache: {:?}", why);
+        // Failing that, letting a duplicate in is better than refusing the
+        // tracks
+        client
+            .playlist_contains(&self.playlist_id, uris)
+            .unwrap_or_else(|why| {
+                error!(
+                    "Could not check the playlist for duplicates: {:?}",
+                    why
+                );
+                vec![false; uris.len()]
+            })
+    }
+
+    /// How many tracks of the playlist credit the artist.
+    pub fn artist_track_count(&self, artist_id: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .artist_track_counts
+            .get(artist_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Looks a track up and adds it to the playlist, unless it is already
+    /// there or by an artist in `blocklist`. Every chat a link can come from
+    /// adds tracks through here.
+    pub fn add_unless_present(
+        &self,
+        client: &mut SpotifyClient,
+        blocklist: &Blocklist,
+        track_id: &str,
+    ) -> Result<AddOutcome, SpotifyError> {
+        let track = client.get_track(track_id)?;
+        let present = self.contains_all(client, &[&track.uri]);
+        self.add_checked(client, blocklist, track, present[0])
+    }
+
+    /// Adds a track already checked against the playlist, unless it was
+    /// found there or is by an artist in `blocklist`.
+    pub fn add_checked(
+        &self,
+        client: &mut SpotifyClient,
+        blocklist: &Blocklist,
+        track: TrackInfo,
+        already_present: bool,
+    ) -> Result<AddOutcome, SpotifyError> {
+        if let Some(artist) = blocklist.blocked_artist(&track) {
+            let artist = artist.to_string();
+            return Ok(AddOutcome::Blocked(track, artist));
+        }
+        if track.is_playable == Some(false) {
+            return Ok(AddOutcome::Unplayable(track));
+        }
+        // Whether the cache matched the playlist right before the add
+        let current_snapshot_id = client
+            .get_playlist_snapshot(&self.playlist_id)
+            .map(|snapshot| snapshot.snapshot_id)
+            .ok();
+        // Checked and claimed under one lock, so two messages linking the
+        // same track can't both add it
+        let expected_snapshot_id = {
+            let mut state = self.state.lock().unwrap();
+            if already_present
+                || state.uris.contains(&track.uri)
+                || !state.pending.insert(track.uri.clone())
+            {
+                return Ok(AddOutcome::AlreadyInPlaylist(track));
+            }
+            state
+                .snapshot_id
+                .clone()
+                .filter(|cached| current_snapshot_id.as_ref() == Some(cached))
+        };
+        let result = client.add_to_playlist(&self.playlist_id, &track.uri);
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(&track.uri);
+        let snapshot_id = result?;
+        // Nothing was written, so recording the track or the placeholder
+        // snapshot would only leave the cache out of step with Spotify
+        if client.is_dry_run() {
+            return Ok(AddOutcome::Added(track));
+        }
+        state.insert(&track);
+        // The new snapshot only describes the cached tracks plus this one if
+        // the cache was current before the add and nothing changed it since.
+        // Otherwise the next refresh reloads.
+        if expected_snapshot_id.is_some()
+            && state.snapshot_id == expected_snapshot_id
+        {
+            state.snapshot_id = Some(snapshot_id);
+        }
+        Ok(AddOutcome::Added(track))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(uri: &str, name: &str, artist: &str) -> TrackInfo {
+        TrackInfo {
+            uri: uri.to_string(),
+            name: name.to_string(),
+            artists: vec![artist.to_string()],
+            artist_ids: vec![artist.to_lowercase()],
+            isrc: None,
+            popularity: 0,
+            duration_ms: 0,
+            preview_url: None,
+            album_image_url: None,
+            is_playable: None,
+            linked_from_uri: None,
+        }
+    }
+
+    fn item(track: Option<TrackInfo>) -> PlaylistItem {
+        PlaylistItem {
+            track,
+            added_at: None,
+            added_by: None,
+            is_local: false,
+        }
+    }
+
+    fn with_isrc(mut track: TrackInfo, isrc: &str) -> TrackInfo {
+        track.isrc = Some(isrc.to_string());
+        track
+    }
+
+    #[test]
+    fn fit_duration_finds_exact_fit() {
+        let durations = [180_000, 200_000, 240_000];
+        assert_eq!(fit_duration(&durations, 420_000, 0), Some(vec![0, 2]));
+    }
+
+    #[test]
+    fn fit_duration_accepts_within_tolerance() {
+        let durations = [100_000, 250_000];
+        assert_eq!(fit_duration(&durations, 340_000, 10_000), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn fit_duration_rejects_outside_tolerance() {
+        let durations = [100_000, 250_000];
+        assert_eq!(fit_duration(&durations, 500_000, 10_000), None);
+        // An item is never used twice to reach the target
+        assert_eq!(fit_duration(&[60_000], 120_000, 0), None);
+    }
+
+    #[test]
+    fn fit_duration_without_items() {
+        assert_eq!(fit_duration(&[], 600_000, 60_000), None);
+    }
+
+    #[test]
+    fn find_duplicates_matches_exact_isrc_and_fuzzy() {
+        let original = with_isrc(track("spotify:track:a", "Song", "Band"), "X");
+        let items = vec![
+            item(Some(original.clone())),
+            item(None),
+            item(Some(original.clone())),
+            item(Some(with_isrc(
+                track("spotify:track:b", "Song", "Band"),
+                "X",
+            ))),
+            item(Some(track(
+                "spotify:track:c",
+                "Song (feat. Someone) - Remastered 2011",
+                "BAND",
+            ))),
+            item(Some(track("spotify:track:d", "Other Song", "Band"))),
+        ];
+        let duplicates =
+            find_duplicates(items.into_iter().map(Ok::<_, Infallible>))
+                .unwrap();
+        let found: Vec<(usize, DuplicateKind)> = duplicates
+            .iter()
+            .map(|duplicate| (duplicate.position, duplicate.kind))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, DuplicateKind::Exact),
+                (3, DuplicateKind::Isrc),
+                (4, DuplicateKind::Fuzzy),
+            ]
+        );
+        assert!(duplicates
+            .iter()
+            .all(|duplicate| duplicate.original.uri == "spotify:track:a"));
+    }
+
+    #[test]
+    fn find_duplicates_stops_at_errors() {
+        let items = vec![Ok(item(None)), Err("page failed")];
+        assert!(find_duplicates(items.into_iter()).is_err());
+    }
+
+    /// Applies `moves` the way Spotify's reorder endpoint does.
+    fn apply(mut items: Vec<PlaylistItem>, moves: &[RangeMove]) -> Vec<String> {
+        for RangeMove {
+            range_start,
+            range_length,
+            insert_before,
+        } in moves
+        {
+            assert!(insert_before < range_start);
+            let range: Vec<PlaylistItem> = items
+                .drain(*range_start..range_start + range_length)
+                .collect();
+            items.splice(*insert_before..*insert_before, range);
+        }
+        items
+            .into_iter()
+            .map(|item| item.track.map(|track| track.name).unwrap_or_default())
+            .collect()
+    }
+
+    fn unsorted() -> Vec<PlaylistItem> {
+        [("c", 30, 3), ("a", 10, 1), ("d", 40, 4), ("b", 20, 2)]
+            .into_iter()
+            .map(|(name, popularity, duration)| {
+                let mut track =
+                    track(&format!("spotify:track:{name}"), name, name);
+                track.popularity = popularity;
+                track.duration_ms = duration;
+                item(Some(track))
+            })
+            .chain([item(None)])
+            .collect()
+    }
+
+    #[test]
+    fn plan_sort_orders_by_each_key_with_unavailable_tracks_last() {
+        let items = unsorted();
+        let by_artist = plan_sort(&items, SortKey::Artist);
+        assert_eq!(apply(unsorted(), &by_artist), ["a", "b", "c", "d", ""]);
+        let by_duration = plan_sort(&items, SortKey::Duration);
+        assert_eq!(apply(unsorted(), &by_duration), ["a", "b", "c", "d", ""]);
+        let by_popularity = plan_sort(&items, SortKey::Popularity);
+        assert_eq!(apply(unsorted(), &by_popularity), ["d", "c", "b", "a", ""]);
+    }
+
+    #[test]
+    fn plan_sort_leaves_sorted_playlists_alone() {
+        let mut items = unsorted();
+        items.sort_by(|a, b| SortKey::Artist.compare(a, b));
+        assert!(plan_sort(&items, SortKey::Artist).is_empty());
+    }
+
+    #[test]
+    fn plan_sort_moves_runs_together() {
+        // Moving "a b" in front of "c d" is a single request
+        let items: Vec<PlaylistItem> = ["c", "d", "a", "b"]
+            .into_iter()
+            .map(|name| item(Some(track(name, name, name))))
+            .collect();
+        let moves = plan_sort(&items, SortKey::Artist);
+        assert_eq!(
+            moves,
+            [RangeMove {
+                range_start: 2,
+                range_length: 2,
+                insert_before: 0,
+            }]
+        );
+    }
+}
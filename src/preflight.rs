@@ -0,0 +1,49 @@
+use log::warn;
+
+use crate::spotify_client::{FeedbackKind, SpotifyClient};
+
+/// Checks that the logged in Spotify account can add tracks to a playlist,
+/// which it can if it owns the playlist or the playlist is collaborative.
+/// The error says what to change, rather than leaving it to a 403 on the
+/// first track added.
+pub fn check_playlist_access(
+    client: &mut SpotifyClient,
+    playlist_id: &str,
+) -> Result<(), String> {
+    let user_id = client.get_current_user_id().map_err(|why| {
+        format!("Could not look up the Spotify account: {why}")
+    })?;
+    let snapshot = match client.get_playlist_snapshot(playlist_id) {
+        Ok(snapshot) => snapshot,
+        Err(why) if why.feedback_kind() == FeedbackKind::NotFound => {
+            return Err(format!(
+                "Playlist {playlist_id} does not exist, or is private to an \
+                 account other than {user_id}"
+            ))
+        }
+        Err(why) => {
+            return Err(format!(
+                "Could not look up playlist {playlist_id}: {why}"
+            ))
+        }
+    };
+
+    if snapshot.owner_id == user_id {
+        return Ok(());
+    }
+    if !snapshot.collaborative {
+        return Err(format!(
+            "Playlist \"{}\" is owned by {} and is not collaborative, so {} \
+             cannot add to it. Log the bot in as {} or make the playlist \
+             collaborative.",
+            snapshot.name, snapshot.owner_id, user_id, snapshot.owner_id
+        ));
+    }
+    // Spotify does not list collaborators, so this is as far as we can check
+    warn!(
+        "Playlist \"{}\" is owned by {}; {} can only add to it if it has \
+         joined as a collaborator",
+        snapshot.name, snapshot.owner_id, user_id
+    );
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::error;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+
+// Shorter operations finish before a progress message would be worth reading
+const MIN_ITEMS: usize = 10;
+// Discord allows about five edits to a message every five seconds
+const MIN_EDIT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// One message edited in place to show how far a long operation has got,
+/// such as "Importing: 42/180 tracks…", instead of a message per step.
+/// Operations with fewer than `MIN_ITEMS` items post nothing.
+pub struct Progress {
+    http: Arc<Http>,
+    message: Option<Message>,
+    label: String,
+    done: usize,
+    total: usize,
+    last_edit: Instant,
+}
+
+impl Progress {
+    /// Posts the progress message to `channel_id`, if the operation is long
+    /// enough to need one.
+    pub async fn start(
+        http: &Arc<Http>,
+        channel_id: ChannelId,
+        label: &str,
+        total: usize,
+    ) -> Progress {
+        let message = if total >= MIN_ITEMS {
+            channel_id
+                .say(http, format!("{label}: 0/{total}…"))
+                .await
+                .map_err(|why| error!("Could not post progress: {:?}", why))
+                .ok()
+        } else {
+            None
+        };
+        Progress {
+            http: http.clone(),
+            message,
+            label: label.to_string(),
+            done: 0,
+            total,
+            last_edit: Instant::now(),
+        }
+    }
+
+    /// Counts one more item as done. The message is only edited every few
+    /// seconds, to stay clear of Discord's rate limit.
+    pub async fn advance(&mut self) {
+        self.done += 1;
+        if self.last_edit.elapsed() < MIN_EDIT_INTERVAL {
+            return;
+        }
+        self.last_edit = Instant::now();
+        let content = format!("{}: {}/{}…", self.label, self.done, self.total);
+        if let Some(message) = &mut self.message {
+            if let Err(why) =
+                message.edit(&self.http, |edit| edit.content(content)).await
+            {
+                error!("Could not update progress: {:?}", why);
+            }
+        }
+    }
+
+    /// Removes the progress message, once the operation's result has been
+    /// posted.
+    pub async fn finish(self) {
+        if let Some(message) = self.message {
+            if let Err(why) = message.delete(&self.http).await {
+                error!("Could not remove progress: {:?}", why);
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use serenity::model::id::{ChannelId, UserId};
+
+use crate::playlist_manager::{normalize, normalize_title};
+use crate::spotify_client::TrackInfo;
+
+/// A track waiting for its first correct guess.
+struct Round {
+    id: u64,
+    track: TrackInfo,
+}
+
+/// Guess-the-track rounds, at most one per channel, and the points players
+/// have won so far. Scores only live as long as the process.
+#[derive(Default)]
+pub struct Quiz {
+    rounds: HashMap<ChannelId, Round>,
+    scores: HashMap<UserId, u32>,
+    next_round_id: u64,
+}
+
+impl Quiz {
+    /// Starts a round in `channel_id` and returns its ID, or `None` if the
+    /// channel already has one running.
+    pub fn start(
+        &mut self,
+        channel_id: ChannelId,
+        track: TrackInfo,
+    ) -> Option<u64> {
+        if self.rounds.contains_key(&channel_id) {
+            return None;
+        }
+        self.next_round_id += 1;
+        let id = self.next_round_id;
+        self.rounds.insert(channel_id, Round { id, track });
+        Some(id)
+    }
+
+    /// Ends the channel's round if `guess` names its title or one of its
+    /// artists, awarding `user_id` a point. Returns the track and the user's
+    /// new score.
+    pub fn guess(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        guess: &str,
+    ) -> Option<(TrackInfo, u32)> {
+        let round = self.rounds.get(&channel_id)?;
+        if !is_correct(&round.track, guess) {
+            return None;
+        }
+        let round = self.rounds.remove(&channel_id)?;
+        let score = self.scores.entry(user_id).or_default();
+        *score += 1;
+        Some((round.track, *score))
+    }
+
+    /// Ends round `round_id` without a winner, unless it was already won.
+    pub fn expire(
+        &mut self,
+        channel_id: ChannelId,
+        round_id: u64,
+    ) -> Option<TrackInfo> {
+        match self.rounds.get(&channel_id) {
+            Some(round) if round.id == round_id => {
+                self.rounds.remove(&channel_id).map(|round| round.track)
+            }
+            _ => None,
+        }
+    }
+
+    /// Players by descending score.
+    pub fn leaderboard(&self) -> Vec<(UserId, u32)> {
+        let mut scores: Vec<(UserId, u32)> = self
+            .scores
+            .iter()
+            .map(|(&user, &score)| (user, score))
+            .collect();
+        scores.sort_by_key(|&(user, score)| (Reverse(score), user));
+        scores
+    }
+}
+
+fn is_correct(track: &TrackInfo, guess: &str) -> bool {
+    let guess = normalize(guess);
+    !guess.is_empty()
+        && (guess == normalize_title(&track.name)
+            || track
+                .artists
+                .iter()
+                .any(|artist| guess == normalize(artist)))
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serenity::model::id::UserId;
+
+use crate::config::env_or;
+
+const WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A member linked more tracks than their quota has room for.
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    /// How many more tracks the member may link right now.
+    pub remaining: usize,
+    /// Until enough earlier submissions age out to make room for the
+    /// rejected ones. `None` if there won't ever be enough room.
+    pub retry_in: Option<Duration>,
+}
+
+impl QuotaExceeded {
+    /// Explains the refusal of a message linking `count` tracks.
+    pub fn reason(&self, count: usize) -> String {
+        match self.remaining {
+            0 => "You've reached your daily track limit".to_string(),
+            1 => format!(
+                "You can link 1 more track today, this message links {count}"
+            ),
+            remaining => format!(
+                "You can link {remaining} more tracks today, \
+                 this message links {count}"
+            ),
+        }
+    }
+}
+
+/// Refuses a message linking more than `MAX_LINKS_PER_MESSAGE` tracks (0,
+/// the default, allows any number), explaining why.
+pub fn check_link_count(count: usize) -> Result<(), String> {
+    let max_links = env_or("MAX_LINKS_PER_MESSAGE", 0usize);
+    if max_links > 0 && count > max_links {
+        return Err(format!(
+            "A message can link at most {max_links} tracks, \
+             this one links {count}"
+        ));
+    }
+    Ok(())
+}
+
+/// How many tracks each member may link in any 24 hours, from
+/// `MAX_TRACKS_PER_USER_PER_DAY` (0, the default, turns this off). Counts are
+/// kept in memory, so a restart forgets them. Members are Discord users unless
+/// `K` says otherwise.
+pub struct SubmissionQuota<K = UserId> {
+    submissions: Mutex<HashMap<K, VecDeque<Instant>>>,
+}
+
+impl<K> Default for SubmissionQuota<K> {
+    fn default() -> Self {
+        SubmissionQuota {
+            submissions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash> SubmissionQuota<K> {
+    /// Counts `count` tracks linked by the member, or none of them if they
+    /// don't all fit in the quota.
+    pub fn take(&self, user_id: K, count: usize) -> Result<(), QuotaExceeded> {
+        let max = env_or("MAX_TRACKS_PER_USER_PER_DAY", 0usize);
+        self.take_at(user_id, count, max, Instant::now())
+    }
+
+    fn take_at(
+        &self,
+        user_id: K,
+        count: usize,
+        max: usize,
+        now: Instant,
+    ) -> Result<(), QuotaExceeded> {
+        if max == 0 {
+            return Ok(());
+        }
+        let mut submissions = self.submissions.lock().unwrap();
+        let times = submissions.entry(user_id).or_default();
+        while times
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= WINDOW)
+        {
+            times.pop_front();
+        }
+        let remaining = max.saturating_sub(times.len());
+        if count > remaining {
+            // The oldest submissions free up room first
+            let must_expire = times.len() + count - max;
+            let retry_in = times
+                .get(must_expire - 1)
+                .map(|&at| WINDOW - now.duration_since(at));
+            return Err(QuotaExceeded {
+                remaining,
+                retry_in,
+            });
+        }
+        times.extend(std::iter::repeat_n(now, count));
+        Ok(())
+    }
+
+    /// Gives back the last `count` tracks taken from the member, for links
+    /// that didn't end up in the playlist.
+    pub fn refund(&self, user_id: &K, count: usize) {
+        if count == 0 {
+            return;
+        }
+        let mut submissions = self.submissions.lock().unwrap();
+        if let Some(times) = submissions.get_mut(user_id) {
+            times.truncate(times.len().saturating_sub(count));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+    const MEMBER: UserId = UserId(1);
+
+    #[test]
+    fn zero_turns_the_quota_off() {
+        let quota = SubmissionQuota::default();
+        assert!(quota.take_at(MEMBER, 1000, 0, Instant::now()).is_ok());
+    }
+
+    #[test]
+    fn takes_all_or_nothing() {
+        let quota = SubmissionQuota::default();
+        let now = Instant::now();
+        assert!(quota.take_at(MEMBER, 3, 5, now).is_ok());
+        let exceeded = quota.take_at(MEMBER, 3, 5, now).unwrap_err();
+        assert_eq!(exceeded.remaining, 2);
+        // The rejected tracks weren't counted
+        assert!(quota.take_at(MEMBER, 2, 5, now).is_ok());
+        // Other members have their own quota
+        assert!(quota.take_at(UserId(2), 5, 5, now).is_ok());
+    }
+
+    #[test]
+    fn retry_in_waits_for_enough_submissions_to_expire() {
+        let quota = SubmissionQuota::default();
+        let start = Instant::now();
+        quota.take_at(MEMBER, 2, 4, start).unwrap();
+        quota.take_at(MEMBER, 2, 4, start + HOUR).unwrap();
+        // Room for three needs the two oldest and one of the newer two gone
+        let exceeded = quota.take_at(MEMBER, 3, 4, start + 2 * HOUR);
+        let exceeded = exceeded.unwrap_err();
+        assert_eq!(exceeded.remaining, 0);
+        assert_eq!(exceeded.retry_in, Some(WINDOW - HOUR));
+        // Room for one only needs the oldest gone
+        let exceeded = quota.take_at(MEMBER, 1, 4, start + 2 * HOUR);
+        assert_eq!(exceeded.unwrap_err().retry_in, Some(WINDOW - 2 * HOUR));
+    }
+
+    #[test]
+    fn retry_in_is_none_when_the_tracks_never_fit() {
+        let quota = SubmissionQuota::default();
+        let exceeded = quota.take_at(MEMBER, 5, 4, Instant::now());
+        let exceeded = exceeded.unwrap_err();
+        assert_eq!(exceeded.remaining, 4);
+        assert_eq!(exceeded.retry_in, None);
+    }
+
+    #[test]
+    fn refunds_make_room_again() {
+        let quota = SubmissionQuota::default();
+        let now = Instant::now();
+        quota.take_at(MEMBER, 4, 4, now).unwrap();
+        quota.refund(&MEMBER, 3);
+        assert!(quota.take_at(MEMBER, 3, 4, now).is_ok());
+        assert!(quota.take_at(MEMBER, 1, 4, now).is_err());
+        // Refunding more than was taken, or for a stranger, is harmless
+        quota.refund(&MEMBER, 10);
+        quota.refund(&UserId(2), 1);
+        assert!(quota.take_at(MEMBER, 4, 4, now).is_ok());
+    }
+
+    #[test]
+    fn submissions_expire_after_a_day() {
+        let quota = SubmissionQuota::default();
+        let start = Instant::now();
+        quota.take_at(MEMBER, 4, 4, start).unwrap();
+        assert!(quota.take_at(MEMBER, 1, 4, start + WINDOW - HOUR).is_err());
+        assert!(quota.take_at(MEMBER, 4, 4, start + WINDOW).is_ok());
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+// Reset headers below this are a number of seconds rather than a timestamp
+const MIN_RESET_TIMESTAMP: f64 = 1_000_000_000.0;
+// Spotify has asked for pauses of hours. Requests made after this long are
+// rate limited again if it meant it.
+const MAX_PAUSE: Duration = Duration::from_secs(60);
+
+/// Holds back requests after the API asked the bot to slow down. Clones share
+/// the same pause, so one rate limited request pauses every thread.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    paused_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RateLimiter {
+    /// Blocks until any pause has passed. Fails with the time left instead
+    /// when the pause runs past `deadline`.
+    pub fn wait(&self, deadline: Instant) -> Result<(), Duration> {
+        let paused_until = *self.paused_until.lock().unwrap();
+        let Some(paused_until) = paused_until else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let remaining = paused_until.saturating_duration_since(now);
+        if now + remaining > deadline {
+            return Err(remaining);
+        }
+        if !remaining.is_zero() {
+            thread::sleep(remaining);
+        }
+        Ok(())
+    }
+
+    /// Pauses requests for `duration`, at most a minute, unless a longer
+    /// pause is already running.
+    pub fn pause_for(&self, duration: Duration) {
+        let duration = duration.min(MAX_PAUSE);
+        warn!("Rate limited, pausing requests for {:?}", duration);
+        let until = Instant::now() + duration;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_none_or(|current| current < until) {
+            *paused_until = Some(until);
+        }
+    }
+}
+
+/// How long a rate limited response asks the client to wait. `Retry-After`
+/// may be whole or fractional seconds or an HTTP date; failing that, an
+/// `X-RateLimit-Reset` header holding either seconds or a Unix timestamp is
+/// used.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(value) = header_str(headers, RETRY_AFTER.as_str()) {
+        if let Some(duration) = parse_seconds(value) {
+            return Some(duration);
+        }
+        if let Ok(date) = httpdate::parse_http_date(value) {
+            return Some(
+                date.duration_since(SystemTime::now()).unwrap_or_default(),
+            );
+        }
+    }
+    let reset = header_str(headers, "x-ratelimit-reset")?
+        .parse::<f64>()
+        .ok()?;
+    if reset >= MIN_RESET_TIMESTAMP {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        Some(Duration::try_from_secs_f64(reset).ok()?.saturating_sub(now))
+    } else {
+        Duration::try_from_secs_f64(reset).ok()
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok().map(str::trim)
+}
+
+fn parse_seconds(value: &str) -> Option<Duration> {
+    let seconds: f64 = value.parse().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn unix_now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// Whether `duration` is `expected` give or take a second of test time.
+    fn about(duration: Option<Duration>, expected: u64) -> bool {
+        duration
+            .is_some_and(|duration| duration.as_secs().abs_diff(expected) <= 1)
+    }
+
+    #[test]
+    fn retry_after_reads_seconds() {
+        let wait = retry_after(&headers(&[("retry-after", "3")]));
+        assert_eq!(wait, Some(Duration::from_secs(3)));
+        let wait = retry_after(&headers(&[("retry-after", " 0.5 ")]));
+        assert_eq!(wait, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn retry_after_reads_http_dates() {
+        let later = SystemTime::now() + Duration::from_secs(60);
+        let date = httpdate::fmt_http_date(later);
+        assert!(about(retry_after(&headers(&[("retry-after", &date)])), 60));
+        // A date already past means retrying straight away
+        let date = httpdate::fmt_http_date(UNIX_EPOCH);
+        let wait = retry_after(&headers(&[("retry-after", &date)]));
+        assert_eq!(wait, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn rate_limit_reset_is_seconds_or_a_timestamp() {
+        let wait = retry_after(&headers(&[("x-ratelimit-reset", "10")]));
+        assert_eq!(wait, Some(Duration::from_secs(10)));
+        let reset = (unix_now() + 30).to_string();
+        let wait = retry_after(&headers(&[("x-ratelimit-reset", &reset)]));
+        assert!(about(wait, 30));
+    }
+
+    #[test]
+    fn retry_after_ignores_garbage() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        for garbage in ["soon", "-1", "NaN", "inf", ""] {
+            let wait = retry_after(&headers(&[("retry-after", garbage)]));
+            assert_eq!(wait, None, "{garbage:?}");
+        }
+        let wait = retry_after(&headers(&[("x-ratelimit-reset", "later")]));
+        assert_eq!(wait, None);
+        // A garbled Retry-After falls back to the reset header
+        let wait = retry_after(&headers(&[
+            ("retry-after", "soon"),
+            ("x-ratelimit-reset", "5"),
+        ]));
+        assert_eq!(wait, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn waits_past_the_deadline_fail_instead_of_sleeping() {
+        let limiter = RateLimiter::default();
+        assert_eq!(limiter.wait(Instant::now()), Ok(()));
+        limiter.pause_for(Duration::from_secs(3 * 60 * 60));
+        let remaining = limiter
+            .wait(Instant::now() + Duration::from_secs(30))
+            .unwrap_err();
+        // Hours long pauses are capped
+        assert!(remaining <= MAX_PAUSE);
+        assert!(remaining > Duration::from_secs(30));
+    }
+}
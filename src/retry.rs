@@ -0,0 +1,101 @@
+use std::fmt::Display;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::blocking;
+use crate::config::env_or;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_DEADLINE_SECS: u64 = 30;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// How often and for how long a failing operation is retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    /// Delay before the second attempt, doubled for every attempt after.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Time budget for all attempts together. No attempt is started that
+    /// would have to wait past it.
+    pub deadline: Duration,
+}
+
+impl RetryPolicy {
+    /// Reads `SPOTIFY_RETRY_ATTEMPTS` (default 3, 1 disables retries) and
+    /// `SPOTIFY_RETRY_DEADLINE_SECS` (default 30).
+    pub fn from_env() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: env_or(
+                "SPOTIFY_RETRY_ATTEMPTS",
+                DEFAULT_MAX_ATTEMPTS,
+            ),
+            base_delay: BASE_DELAY,
+            max_delay: MAX_DELAY,
+            deadline: Duration::from_secs(env_or(
+                "SPOTIFY_RETRY_DEADLINE_SECS",
+                DEFAULT_DEADLINE_SECS,
+            )),
+        }
+    }
+
+    fn delay_after(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay)
+    }
+}
+
+/// Errors that know whether the operation that caused them is worth
+/// repeating.
+pub trait Retryable {
+    /// `idempotent` says whether repeating an operation that did take effect
+    /// is harmless. When it is not, only failures that show the operation
+    /// never took effect should be retried.
+    fn is_retryable(&self, idempotent: bool) -> bool;
+}
+
+/// Runs `op` until it succeeds, fails with an error that is not worth
+/// retrying, or `policy` runs out of attempts or time. `op` is given the
+/// deadline for all attempts, so that waits of its own can respect it.
+/// `op` and the sleeps between attempts block, so they run through
+/// `blocking::run` and are safe to call from async handlers.
+pub fn with_retry<T, E: Retryable + Display>(
+    policy: &RetryPolicy,
+    idempotent: bool,
+    op: impl FnMut(Instant) -> Result<T, E>,
+) -> Result<T, E> {
+    blocking::run(|| retry_blocking(policy, idempotent, op))
+}
+
+fn retry_blocking<T, E: Retryable + Display>(
+    policy: &RetryPolicy,
+    idempotent: bool,
+    mut op: impl FnMut(Instant) -> Result<T, E>,
+) -> Result<T, E> {
+    let started = Instant::now();
+    let deadline = started + policy.deadline;
+    let mut attempt = 1;
+    loop {
+        let why = match op(deadline) {
+            Ok(value) => return Ok(value),
+            Err(why) => why,
+        };
+        let delay = policy.delay_after(attempt);
+        if attempt >= policy.max_attempts
+            || !why.is_retryable(idempotent)
+            || started.elapsed() + delay > policy.deadline
+        {
+            return Err(why);
+        }
+        warn!(
+            "Attempt {} failed, retrying in {:?}: {}",
+            attempt, delay, why
+        );
+        thread::sleep(delay);
+        attempt += 1;
+    }
+}
@@ -0,0 +1,40 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use log::error;
+
+const DOCKER_SECRETS_DIR: &str = "/run/secrets";
+
+/// Looks up a secret by its environment variable name. In order, it is read
+/// from:
+/// - the variable itself, e.g. `DISCORD_TOKEN`
+/// - the file named by the variable with a `_FILE` suffix, e.g.
+///   `DISCORD_TOKEN_FILE`
+/// - a Docker secret named after the variable in lowercase, e.g.
+///   `/run/secrets/discord_token`
+pub fn read(name: &str) -> Option<String> {
+    if let Ok(value) = env::var(name) {
+        return Some(value);
+    }
+    if let Ok(path) = env::var(format!("{name}_FILE")) {
+        return read_file(Path::new(&path));
+    }
+    let docker_secret = Path::new(DOCKER_SECRETS_DIR).join(name.to_lowercase());
+    if docker_secret.exists() {
+        return read_file(&docker_secret);
+    }
+    None
+}
+
+fn read_file(path: &Path) -> Option<String> {
+    match fs::read_to_string(path) {
+        // Editors and `echo` leave a trailing newline that is not part of the
+        // secret
+        Ok(contents) => Some(contents.trim_end().to_string()),
+        Err(why) => {
+            error!("Could not read secret file {}: {:?}", path.display(), why);
+            None
+        }
+    }
+}
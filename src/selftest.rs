@@ -0,0 +1,217 @@
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
+
+use crate::config::{self, env_or, Severity};
+use crate::http_client;
+use crate::preflight;
+use crate::secrets;
+use crate::spotify_client::{self, SpotifyClient};
+
+// Channels the bot posts to on its own, if configured
+const CHANNEL_SETTINGS: &[&str] = &[
+    "NEW_RELEASES_CHANNEL_ID",
+    "SONG_OF_THE_DAY_CHANNEL_ID",
+    "QUARANTINE_CHANNEL_ID",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+    Pass,
+    /// Works, but some feature won't.
+    Warn,
+    Fail,
+    /// Not configured, or not checkable after an earlier failure.
+    Skip,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+            Status::Skip => "skip",
+        }
+    }
+}
+
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+/// The outcome of every self-test check, in the order they ran.
+#[derive(Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn add(&mut self, name: &str, status: Status, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    /// Whether no check failed. Warnings and skipped checks still pass.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.status != Status::Fail)
+    }
+
+    pub fn log(&self) {
+        for check in &self.checks {
+            match check.status {
+                Status::Pass | Status::Skip => {
+                    info!("{}: {}", check.name, check.detail)
+                }
+                Status::Warn => warn!("{}: {}", check.name, check.detail),
+                Status::Fail => error!("{}: {}", check.name, check.detail),
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let checks: Vec<Value> = self
+            .checks
+            .iter()
+            .map(|check| {
+                json!({
+                    "name": check.name,
+                    "status": check.status.name(),
+                    "detail": check.detail,
+                })
+            })
+            .collect();
+        json!({ "passed": self.passed(), "checks": checks })
+    }
+}
+
+/// Validates the configuration, then checks the Spotify login and its scopes, access to the
+/// playlists the bot writes to and the channels it posts to.
+pub async fn run() -> Report {
+    let mut report = Report::default();
+    let validation = config::validate();
+    if validation.problems.is_empty() {
+        report.add("config", Status::Pass, "All settings are valid");
+    }
+    for problem in &validation.problems {
+        let status = match problem.severity {
+            Severity::Error => Status::Fail,
+            Severity::Warning => Status::Warn,
+        };
+        report.add(problem.setting, status, problem.message.clone());
+    }
+    if validation.has_errors() {
+        report.add("spotify", Status::Skip, "Needs a valid config");
+        report.add("discord", Status::Skip, "Needs a valid config");
+        return report;
+    }
+
+    check_spotify(&mut report);
+    if !config::discord_enabled() {
+        report.add("discord", Status::Skip, "DISCORD_ENABLED is false");
+        return report;
+    }
+    let token = secrets::read("DISCORD_TOKEN").unwrap_or_default();
+    check_channels(&mut report, &Http::new(&token)).await;
+    report
+}
+
+fn check_spotify(report: &mut Report) {
+    // Fails on its own if a required scope is missing
+    let market = config::spotify_market().unwrap_or_default();
+    let mut client = SpotifyClient::new(http_client::build(), market);
+    match client.get_current_user_id() {
+        Ok(user_id) => report.add(
+            "spotify auth",
+            Status::Pass,
+            format!("Logged in as {user_id}"),
+        ),
+        Err(why) => {
+            report.add("spotify auth", Status::Fail, why.to_string());
+            return;
+        }
+    }
+
+    let missing_scopes = client.missing_optional_scopes();
+    if missing_scopes.is_empty() {
+        report.add("spotify scopes", Status::Pass, "All scopes granted");
+    } else {
+        let missing: Vec<String> = missing_scopes
+            .iter()
+            .map(|(scope, feature)| format!("{scope} ({feature})"))
+            .collect();
+        report.add(
+            "spotify scopes",
+            Status::Warn,
+            format!("Missing {}", missing.join(", ")),
+        );
+    }
+
+    check_playlist(
+        report,
+        &mut client,
+        "collaborative playlist",
+        spotify_client::PLAYLIST_ID,
+    );
+    let discover_playlist_id = env_or("DISCOVER_PLAYLIST_ID", String::new());
+    if discover_playlist_id.is_empty() {
+        report.add(
+            "discover playlist",
+            Status::Skip,
+            "DISCOVER_PLAYLIST_ID is not set",
+        );
+    } else {
+        check_playlist(
+            report,
+            &mut client,
+            "discover playlist",
+            &discover_playlist_id,
+        );
+    }
+}
+
+fn check_playlist(
+    report: &mut Report,
+    client: &mut SpotifyClient,
+    name: &str,
+    playlist_id: &str,
+) {
+    match preflight::check_playlist_access(client, playlist_id) {
+        Ok(()) => report.add(
+            name,
+            Status::Pass,
+            format!("Can modify {}", spotify_client::playlist_url(playlist_id)),
+        ),
+        Err(why) => report.add(name, Status::Fail, why),
+    }
+}
+
+async fn check_channels(report: &mut Report, http: &Http) {
+    for setting in CHANNEL_SETTINGS {
+        let channel_id = env_or(setting, 0u64);
+        if channel_id == 0 {
+            report.add(setting, Status::Skip, "Not set");
+            continue;
+        }
+        match ChannelId(channel_id).to_channel(http).await {
+            Ok(channel) => {
+                let name = channel
+                    .guild()
+                    .map(|channel| format!("#{}", channel.name))
+                    .unwrap_or_else(|| channel_id.to_string());
+                report.add(setting, Status::Pass, format!("Can see {name}"));
+            }
+            Err(why) => report.add(
+                setting,
+                Status::Fail,
+                format!("Cannot see channel {channel_id}: {why}"),
+            ),
+        }
+    }
+}
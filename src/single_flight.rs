@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Collapses concurrent calls with the same key into one. The first caller
+/// runs the call and every caller that arrives before it finishes gets a
+/// clone of its result. Clones share the calls in flight. Callers block, so
+/// from async code go through `blocking::run`.
+#[derive(Clone)]
+pub struct SingleFlight<T> {
+    calls: Arc<Mutex<HashMap<String, Arc<Call<T>>>>>,
+}
+
+struct Call<T> {
+    /// `None` until the call finishes, and still `None` after that if the
+    /// caller running it panicked.
+    result: Mutex<Option<T>>,
+    finished: Mutex<bool>,
+    done: Condvar,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> SingleFlight<T> {
+        SingleFlight {
+            calls: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    /// Runs `call`, unless a call with the same `key` is already running, in
+    /// which case this blocks until it finishes and returns its result.
+    pub fn run(&self, key: &str, call: impl FnOnce() -> T) -> T {
+        let (in_flight, leader) = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get(key) {
+                Some(in_flight) => (in_flight.clone(), false),
+                None => {
+                    let in_flight = Arc::new(Call {
+                        result: Mutex::new(None),
+                        finished: Mutex::new(false),
+                        done: Condvar::new(),
+                    });
+                    calls.insert(key.to_string(), in_flight.clone());
+                    (in_flight, true)
+                }
+            }
+        };
+
+        if !leader {
+            let mut finished = in_flight.finished.lock().unwrap();
+            while !*finished {
+                finished = in_flight.done.wait(finished).unwrap();
+            }
+            drop(finished);
+            // The leader panicked, so make the call ourselves
+            return match in_flight.result.lock().unwrap().clone() {
+                Some(result) => result,
+                None => call(),
+            };
+        }
+
+        // Wakes the waiters even if `call` panics, so they don't hang
+        let _finish = Finish {
+            calls: &self.calls,
+            key,
+            in_flight: &in_flight,
+        };
+        let result = call();
+        *in_flight.result.lock().unwrap() = Some(result.clone());
+        result
+    }
+}
+
+struct Finish<'a, T> {
+    calls: &'a Mutex<HashMap<String, Arc<Call<T>>>>,
+    key: &'a str,
+    in_flight: &'a Call<T>,
+}
+
+impl<T> Drop for Finish<'_, T> {
+    fn drop(&mut self) {
+        // A poisoned lock still holds valid data, the panic was in `call`
+        let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        calls.remove(self.key);
+        drop(calls);
+        *self
+            .in_flight
+            .finished
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = true;
+        self.in_flight.done.notify_all();
+    }
+}
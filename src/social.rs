@@ -0,0 +1,251 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, info};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+
+use crate::config::{self, env_or};
+use crate::secrets;
+
+const MASTODON_MAX_CHARS: usize = 500;
+// Bluesky counts graphemes, which is at most the number of characters
+const BLUESKY_MAX_CHARS: usize = 300;
+const DEFAULT_BLUESKY_SERVICE_URL: &str = "https://bsky.social";
+
+enum Account {
+    Mastodon {
+        instance_url: String,
+        access_token: String,
+    },
+    Bluesky {
+        service_url: String,
+        handle: String,
+        app_password: String,
+    },
+}
+
+impl Account {
+    fn name(&self) -> &'static str {
+        match self {
+            Account::Mastodon { .. } => "Mastodon",
+            Account::Bluesky { .. } => "Bluesky",
+        }
+    }
+
+    fn max_chars(&self) -> usize {
+        match self {
+            Account::Mastodon { .. } => MASTODON_MAX_CHARS,
+            Account::Bluesky { .. } => BLUESKY_MAX_CHARS,
+        }
+    }
+}
+
+/// Cross-posts announcements to a Mastodon account, a Bluesky account or
+/// both.
+pub struct Social {
+    http_client: Client,
+    accounts: Vec<Account>,
+}
+
+impl Social {
+    /// `None` unless `MASTODON_INSTANCE_URL` and a `MASTODON_ACCESS_TOKEN`,
+    /// or `BLUESKY_HANDLE` and a `BLUESKY_APP_PASSWORD`, are set. Bluesky
+    /// accounts hosted elsewhere than bsky.social also need
+    /// `BLUESKY_SERVICE_URL`.
+    pub fn from_env(http_client: Client) -> Option<Social> {
+        let mut accounts = Vec::new();
+        let instance_url = env_or("MASTODON_INSTANCE_URL", String::new());
+        let access_token = secrets::read("MASTODON_ACCESS_TOKEN");
+        if let Some(access_token) =
+            access_token.filter(|_| !instance_url.is_empty())
+        {
+            accounts.push(Account::Mastodon {
+                instance_url: instance_url.trim_end_matches('/').to_string(),
+                access_token,
+            });
+        }
+        let handle = env_or("BLUESKY_HANDLE", String::new());
+        let app_password = secrets::read("BLUESKY_APP_PASSWORD");
+        if let Some(app_password) = app_password.filter(|_| !handle.is_empty())
+        {
+            let service_url = env_or(
+                "BLUESKY_SERVICE_URL",
+                DEFAULT_BLUESKY_SERVICE_URL.to_string(),
+            );
+            accounts.push(Account::Bluesky {
+                service_url: service_url.trim_end_matches('/').to_string(),
+                handle,
+                app_password,
+            });
+        }
+        if accounts.is_empty() {
+            return None;
+        }
+        Some(Social {
+            http_client,
+            accounts,
+        })
+    }
+
+    /// Posts `heading`, `link` and as many of `lines` as fit to every
+    /// account. Failures are only logged, announcements are not worth
+    /// retrying. Nothing is posted in a dry run.
+    pub fn post(&self, heading: &str, link: &str, lines: &[String]) {
+        for account in &self.accounts {
+            if config::dry_run() {
+                info!(
+                    "Dry run, not posting \"{}\" to {}",
+                    heading,
+                    account.name()
+                );
+                continue;
+            }
+            let text = compose(heading, link, lines, account.max_chars());
+            let result = match account {
+                Account::Mastodon {
+                    instance_url,
+                    access_token,
+                } => self.post_to_mastodon(instance_url, access_token, &text),
+                Account::Bluesky {
+                    service_url,
+                    handle,
+                    app_password,
+                } => self.post_to_bluesky(
+                    service_url,
+                    handle,
+                    app_password,
+                    &text,
+                    link,
+                ),
+            };
+            match result {
+                Ok(()) => info!("Posted \"{}\" to {}", heading, account.name()),
+                Err(why) => {
+                    error!("Could not post to {}: {:?}", account.name(), why)
+                }
+            }
+        }
+    }
+
+    fn post_to_mastodon(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        text: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.http_client
+            .post(format!("{instance_url}/api/v1/statuses"))
+            .bearer_auth(access_token)
+            .form(&[("status", text)])
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Logs in with the app password and creates the post. Bluesky only
+    /// makes `link` clickable when a facet marks where it is in the text.
+    fn post_to_bluesky(
+        &self,
+        service_url: &str,
+        handle: &str,
+        app_password: &str,
+        text: &str,
+        link: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let session: Value = self
+            .http_client
+            .post(format!(
+                "{service_url}/xrpc/com.atproto.server.createSession"
+            ))
+            .json(&json!({ "identifier": handle, "password": app_password }))
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let access_jwt = session["accessJwt"]
+            .as_str()
+            .ok_or("Bluesky session has no access token")?;
+        let did = session["did"]
+            .as_str()
+            .ok_or("Bluesky session has no DID")?;
+
+        let mut record = json!({
+            "$type": "app.bsky.feed.post",
+            "text": text,
+            "createdAt": rfc3339_now(),
+        });
+        if let Some(start) = text.find(link) {
+            record["facets"] = json!([{
+                "index": { "byteStart": start, "byteEnd": start + link.len() },
+                "features": [{
+                    "$type": "app.bsky.richtext.facet#link",
+                    "uri": link,
+                }],
+            }]);
+        }
+        self.http_client
+            .post(format!("{service_url}/xrpc/com.atproto.repo.createRecord"))
+            .bearer_auth(access_jwt)
+            .json(&json!({
+                "repo": did,
+                "collection": "app.bsky.feed.post",
+                "record": record,
+            }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// The heading and link, followed by the lines that fit in `max_chars`.
+fn compose(
+    heading: &str,
+    link: &str,
+    lines: &[String],
+    max_chars: usize,
+) -> String {
+    let mut text = format!("{heading}\n{link}\n");
+    let mut chars = text.chars().count();
+    for line in lines {
+        let line_chars = line.chars().count() + 1;
+        if chars + line_chars > max_chars {
+            break;
+        }
+        text.push('\n');
+        text.push_str(line);
+        chars += line_chars;
+    }
+    text.trim_end().to_string()
+}
+
+/// The current UTC time as Bluesky wants it, such as `2024-05-01T12:00:00Z`.
+fn rfc3339_now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant's
+    // civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
@@ -0,0 +1,98 @@
+use reqwest::blocking::Client;
+use serde_json::Value;
+use url::Url;
+
+use crate::config::env_or;
+use crate::secrets;
+
+const API_URL: &str = "https://api.song.link/v1-alpha.1/links";
+// Hosts of Songlink's own pages, as opposed to the platforms it links to
+const SONGLINK_HOSTS: &[&str] = &["song.link", "album.link", "odesli.co"];
+
+/// A track as Songlink knows it.
+pub struct SonglinkEntry {
+    /// The page linking the track on every platform Songlink knows.
+    pub page_url: String,
+    pub spotify_track_id: Option<String>,
+}
+
+/// Client for the Songlink (Odesli) API, which matches a track across
+/// streaming platforms.
+#[derive(Clone)]
+pub struct Songlink {
+    http_client: Client,
+    api_key: Option<String>,
+}
+
+impl Songlink {
+    /// `None` unless `SONGLINK_ENABLED` is set. Without a
+    /// `SONGLINK_API_KEY`, Songlink allows only a few requests a minute.
+    pub fn from_env(http_client: Client) -> Option<Songlink> {
+        if !env_or("SONGLINK_ENABLED", false) {
+            return None;
+        }
+        Some(Songlink {
+            http_client,
+            api_key: secrets::read("SONGLINK_API_KEY"),
+        })
+    }
+
+    /// Looks up the track at `url`, a link to any platform Songlink knows.
+    pub fn lookup(
+        &self,
+        url: &str,
+    ) -> Result<SonglinkEntry, Box<dyn std::error::Error>> {
+        let mut params = vec![("url", url)];
+        if let Some(api_key) = &self.api_key {
+            params.push(("key", api_key));
+        }
+        let response: Value = self
+            .http_client
+            .get(Url::parse_with_params(API_URL, &params)?)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let page_url = response["pageUrl"]
+            .as_str()
+            .ok_or("Songlink response has no page URL")?
+            .to_string();
+        let spotify_track_id = response["linksByPlatform"]["spotify"]
+            ["entityUniqueId"]
+            .as_str()
+            .and_then(|entity| {
+                let entity = &response["entitiesByUniqueId"][entity];
+                (entity["type"] == "song").then(|| entity["id"].as_str())?
+            })
+            .map(String::from);
+        Ok(SonglinkEntry {
+            page_url,
+            spotify_track_id,
+        })
+    }
+
+    /// The Spotify track a Songlink page is about. Pages of Spotify tracks
+    /// carry the ID in their URL, anything else is looked up.
+    pub fn resolve_spotify_track(
+        &self,
+        page_url: &Url,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let mut segments = page_url.path().split('/').skip(1);
+        if let (Some("s"), Some(id)) = (segments.next(), segments.next()) {
+            return Ok(Some(id.to_string()));
+        }
+        Ok(self.lookup(page_url.as_str())?.spotify_track_id)
+    }
+}
+
+/// Every Songlink page linked in a message, in order.
+pub fn find_songlink_urls(content: &str) -> Vec<Url> {
+    content
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('<').trim_end_matches('>'))
+        .filter_map(|word| Url::parse(word).ok())
+        .filter(|url| {
+            url.host_str()
+                .is_some_and(|host| SONGLINK_HOSTS.contains(&host))
+        })
+        .collect()
+}
@@ -1,64 +1,423 @@
 use base64;
-use std::env;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use log::{error, info};
+use log::{error, info, warn};
 use open;
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, RequestBuilder, Response};
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
-use reqwest::StatusCode;
+use reqwest::{Method, StatusCode};
 use serde_json::{json, Value};
 use url::Url;
 
+use crate::blocking;
+use crate::config::{self, env_or};
+use crate::metrics::{self, Metrics};
+use crate::rate_limit::{self, RateLimiter};
+use crate::retry::{self, RetryPolicy, Retryable};
+use crate::secrets;
+use crate::single_flight::SingleFlight;
+use crate::ttl_cache::TtlCache;
+
 const API_URL: &str = "https://api.spotify.com/v1";
+const REDIRECT_URI: &str = "http://127.0.0.1:5000/callback";
 // TODO this will eventually be user configurable
-const PLAYLIST_ID: &str = "3nf65T5wXvLYLvT6xvXoLf";
+pub const PLAYLIST_ID: &str = "3nf65T5wXvLYLvT6xvXoLf";
+// The largest page Spotify serves for playlist items
+const PLAYLIST_PAGE_SIZE: usize = 100;
+const MAX_CONCURRENT_PAGE_REQUESTS: usize = 4;
+// New releases only need the latest few albums and singles of an artist
+const RELEASES_PAGE_SIZE: usize = 10;
+// The most tracks Spotify adds to a playlist in one request
+const ADD_BATCH_SIZE: usize = 100;
+// The most artists Spotify returns in one request
+const ARTISTS_BATCH_SIZE: usize = 50;
+// The most tracks Spotify returns audio features for in one request
+const AUDIO_FEATURES_BATCH_SIZE: usize = 100;
+const DEFAULT_CACHE_TTL_SECS: u64 = 60 * 60;
+const DEFAULT_CACHE_SIZE: usize = 1000;
+// Stands in for the IDs Spotify would return from writes skipped by dry runs
+const DRY_RUN_ID: &str = "dry-run";
+// How long to back off when a 429 says nothing about when to retry
+const DEFAULT_RATE_LIMIT_WAIT: Duration = Duration::from_secs(1);
+// Scopes the bot cannot add tracks without
+const REQUIRED_SCOPES: &[&str] = &["playlist-modify-public"];
+// Scopes only some commands need, with the feature that breaks without them
+const OPTIONAL_SCOPES: &[(&str, &str)] = &[
+    ("user-modify-playback-state", "/queue"),
+    ("user-follow-modify", "following artists"),
+];
 
 #[derive(Clone)]
 pub struct SpotifyClient {
     http_client: Client,
-    access_token: String,
+    /// Shared by every clone, so a refresh by one is seen by all.
+    access_token: Arc<RwLock<String>>,
     client_id: String,
     client_secret: String,
     authorization_code: String,
+    /// Space separated scopes the user granted, if Spotify reported them.
+    granted_scopes: Option<String>,
+    rate_limiter: RateLimiter,
+    metrics: Metrics,
+    retry_policy: RetryPolicy,
+    /// Country whose catalogue tracks are looked up in, if configured.
+    market: Option<String>,
+    in_flight_gets: SingleFlight<Result<Value, SpotifyError>>,
+    /// Logs writes instead of sending them, so a test deployment can't
+    /// change real playlists.
+    dry_run: bool,
+    /// Track and artist metadata barely changes, so imports and repeat links
+    /// are served from here.
+    tracks: TtlCache<TrackInfo>,
+    artist_genres: TtlCache<Vec<String>>,
+    tempos: TtlCache<f64>,
+    /// Display names of the Spotify users who added tracks, by user ID.
+    user_names: TtlCache<String>,
+}
+
+/// A track as returned by the Spotify API, reduced to the fields the bot uses.
+#[derive(Clone, Debug)]
+pub struct TrackInfo {
+    pub uri: String,
+    pub name: String,
+    pub artists: Vec<String>,
+    /// Spotify IDs of `artists`, in the same order.
+    pub artist_ids: Vec<String>,
+    pub isrc: Option<String>,
+    pub popularity: u64,
+    pub duration_ms: u64,
+    /// A 30 second MP3 clip, missing for some tracks and markets.
+    pub preview_url: Option<String>,
+    /// The largest album cover Spotify has.
+    pub album_image_url: Option<String>,
+    /// Whether the track can be played in the configured market. Only known
+    /// when `SPOTIFY_MARKET` is set.
+    pub is_playable: Option<bool>,
+    /// The URI that was asked for, when Spotify relinked it to another
+    /// version of the track that is playable in the configured market.
+    pub linked_from_uri: Option<String>,
+}
+
+impl TrackInfo {
+    fn from_json(track: &Value) -> Option<TrackInfo> {
+        let uri = track["uri"].as_str()?.to_string();
+        let name = track["name"].as_str().unwrap_or_default().to_string();
+        let artists = track["artists"]
+            .as_array()
+            .map(|artists| {
+                artists
+                    .iter()
+                    .filter_map(|artist| artist["name"].as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let artist_ids = track["artists"]
+            .as_array()
+            .map(|artists| {
+                artists
+                    .iter()
+                    .filter_map(|artist| artist["id"].as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let isrc = track["external_ids"]["isrc"].as_str().map(String::from);
+        let popularity = track["popularity"].as_u64().unwrap_or_default();
+        let duration_ms = track["duration_ms"].as_u64().unwrap_or_default();
+        let preview_url = track["preview_url"].as_str().map(String::from);
+        let is_playable = track["is_playable"].as_bool();
+        let linked_from_uri =
+            track["linked_from"]["uri"].as_str().map(String::from);
+        // Spotify lists album images widest first
+        let album_image_url = track["album"]["images"][0]["url"]
+            .as_str()
+            .map(String::from);
+        Some(TrackInfo {
+            uri,
+            name,
+            artists,
+            artist_ids,
+            isrc,
+            popularity,
+            duration_ms,
+            preview_url,
+            album_image_url,
+            is_playable,
+            linked_from_uri,
+        })
+    }
+
+    /// The track's page on open.spotify.com.
+    pub fn url(&self) -> String {
+        track_url(self.uri.rsplit(':').next().unwrap_or_default())
+    }
+}
+
+/// The page of the track with ID `track_id` on open.spotify.com.
+pub fn track_url(track_id: &str) -> String {
+    format!("https://open.spotify.com/track/{track_id}")
+}
+
+/// The profile of the Spotify user with ID `user_id` on open.spotify.com.
+pub fn user_url(user_id: &str) -> String {
+    format!("https://open.spotify.com/user/{user_id}")
+}
+
+/// The page of the playlist with ID `playlist_id` on open.spotify.com.
+pub fn playlist_url(playlist_id: &str) -> String {
+    format!("https://open.spotify.com/playlist/{playlist_id}")
+}
+
+/// An entry of a playlist: the track, and when and by whom it was added.
+/// `track` is `None` when the track is no longer available on Spotify.
+#[derive(Clone, Debug)]
+pub struct PlaylistItem {
+    pub track: Option<TrackInfo>,
+    /// ISO 8601 timestamp of when the item was added to the playlist.
+    pub added_at: Option<String>,
+    /// Spotify user ID of whoever added the item.
+    pub added_by: Option<String>,
+    /// A file from the adder's device rather than the Spotify catalogue. Its
+    /// track has a `spotify:local:` URI and no IDs, so it can't be looked up,
+    /// queued or added to another playlist.
+    pub is_local: bool,
+}
+
+/// Lazily pages through a playlist, only requesting the next page once every
+/// item of the previous one has been consumed.
+pub struct PlaylistItems {
+    client: SpotifyClient,
+    next_endpoint: Option<String>,
+    page: std::vec::IntoIter<PlaylistItem>,
+}
+
+impl Iterator for PlaylistItems {
+    type Item = Result<PlaylistItem, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.page.next() {
+                return Some(Ok(item));
+            }
+            let endpoint = self.next_endpoint.take()?;
+            match self.client.make_get_request(&endpoint) {
+                Ok(page) => {
+                    self.next_endpoint =
+                        page["next"].as_str().map(String::from);
+                    self.page = parse_playlist_page(&page).into_iter();
+                }
+                Err(why) => return Some(Err(why.into())),
+            }
+        }
+    }
+}
+
+struct AccessToken {
+    access_token: String,
+    /// Space separated scopes the user granted, if Spotify reported them.
+    scope: Option<String>,
+}
+
+/// The name, size, ownership and current snapshot ID of a playlist. Spotify
+/// changes the snapshot ID whenever the playlist contents change.
+pub struct PlaylistSnapshot {
+    pub name: String,
+    pub snapshot_id: String,
+    pub track_count: usize,
+    pub owner_id: String,
+    pub collaborative: bool,
+}
+
+/// An album or single an artist put out.
+pub struct Release {
+    pub id: String,
+    pub name: String,
+    pub artists: Vec<String>,
+    /// `album`, `single` or `compilation`.
+    pub kind: String,
+    pub release_date: String,
+    pub image_url: Option<String>,
+}
+
+impl Release {
+    pub fn url(&self) -> String {
+        format!("https://open.spotify.com/album/{}", self.id)
+    }
+}
+
+/// Why a Spotify request failed.
+#[derive(Clone, Debug)]
+pub enum SpotifyError {
+    /// No usable response, e.g. a timeout or a body that is not JSON. Shared
+    /// so that requests deduplicated by `SingleFlight` can all return it.
+    Http(Arc<reqwest::Error>),
+    /// Spotify answered with an error status.
+    Api { status: StatusCode, message: String },
+    /// Spotify reported success but the response lacks what was asked for.
+    UnexpectedResponse(String),
+    /// Spotify asked the bot to pause for longer than the request had left,
+    /// so it was not sent.
+    RateLimited(Duration),
+}
+
+/// How a failure should be explained to the member who caused it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeedbackKind {
+    /// The track, playlist or playback device does not exist.
+    NotFound,
+    /// Spotify is throttling the bot.
+    RateLimited,
+    /// Spotify is down or unreachable.
+    Unavailable,
+    /// The bot's Spotify account may not do this.
+    Forbidden,
+    /// Anything else, most likely a bug in the bot.
+    Internal,
+}
+
+impl SpotifyError {
+    /// Reads the message out of Spotify's `{"error": {...}}` body.
+    fn from_response(response: Response) -> SpotifyError {
+        let status = response.status();
+        let body: Value = response.json().unwrap_or_default();
+        let message = body["error"]["message"]
+            .as_str()
+            .or_else(|| status.canonical_reason())
+            .unwrap_or_default()
+            .to_string();
+        SpotifyError::Api { status, message }
+    }
+
+    pub fn feedback_kind(&self) -> FeedbackKind {
+        match self {
+            SpotifyError::Http(why) if why.is_timeout() || why.is_connect() => {
+                FeedbackKind::Unavailable
+            }
+            SpotifyError::Http(_) => FeedbackKind::Internal,
+            // Malformed IDs are rejected with 400 rather than 404
+            SpotifyError::Api { status, .. } => match *status {
+                StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND => {
+                    FeedbackKind::NotFound
+                }
+                StatusCode::TOO_MANY_REQUESTS => FeedbackKind::RateLimited,
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                    FeedbackKind::Forbidden
+                }
+                status if status.is_server_error() => FeedbackKind::Unavailable,
+                _ => FeedbackKind::Internal,
+            },
+            SpotifyError::UnexpectedResponse(_) => FeedbackKind::Internal,
+            SpotifyError::RateLimited(_) => FeedbackKind::RateLimited,
+        }
+    }
+}
+
+impl Retryable for SpotifyError {
+    fn is_retryable(&self, idempotent: bool) -> bool {
+        match self {
+            // The request never reached Spotify
+            SpotifyError::Http(why) if why.is_connect() => true,
+            SpotifyError::Http(why) => why.is_timeout() && idempotent,
+            SpotifyError::Api { status, .. } => match *status {
+                // Rejected before being acted on. The rate limiter holds the
+                // retry back for as long as Spotify asked.
+                StatusCode::TOO_MANY_REQUESTS => true,
+                // Rejected before being acted on. `send` refreshes the token
+                // before the retry.
+                StatusCode::UNAUTHORIZED => true,
+                status => status.is_server_error() && idempotent,
+            },
+            SpotifyError::UnexpectedResponse(_)
+            | SpotifyError::RateLimited(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for SpotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpotifyError::Http(why) => write!(f, "{why}"),
+            SpotifyError::Api { status, message } => {
+                write!(f, "Spotify answered {status}: {message}")
+            }
+            SpotifyError::UnexpectedResponse(why) => write!(f, "{why}"),
+            SpotifyError::RateLimited(pause) => {
+                write!(f, "Spotify asked to pause requests for {pause:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpotifyError {}
+
+impl From<reqwest::Error> for SpotifyError {
+    fn from(why: reqwest::Error) -> SpotifyError {
+        SpotifyError::Http(Arc::new(why))
+    }
 }
 
 impl SpotifyClient {
-    pub fn new() -> SpotifyClient {
-        let client_id = env::var("SPOTIFY_CLIENT_ID")
+    /// Logs in to Spotify. `market` is the validated `SPOTIFY_MARKET`, see
+    /// `config::spotify_market`.
+    pub fn new(http_client: Client, market: Option<String>) -> SpotifyClient {
+        let client_id = secrets::read("SPOTIFY_CLIENT_ID")
             .expect("Expected a spotify client ID the environment");
-        let client_secret = env::var("SPOTIFY_CLIENT_SECRET")
+        let client_secret = secrets::read("SPOTIFY_CLIENT_SECRET")
             .expect("Expected a spotify client secret in the environment");
-        let authorization_code = env::var("SPOTIFY_AUTH_CODE")
-            .expect("Expected a spotify authorization code");
-        let http_client = Client::new();
+        let authorization_code = match secrets::read("SPOTIFY_AUTH_CODE") {
+            Some(authorization_code) => authorization_code,
+            None => SpotifyClient::authorize_headless(&client_id)
+                .expect("Expected a spotify authorization code"),
+        };
         // SpotifyClient::authorize_app(&client_id, &http_client);
-        let access_token = SpotifyClient::get_access_token(
+        let token = SpotifyClient::get_access_token(
             &client_id,
             &client_secret,
             &http_client,
             &authorization_code,
         )
         .unwrap();
-        // let access_token = String::new();
+        if let Some(scope) = &token.scope {
+            SpotifyClient::check_scopes(scope);
+        }
+        let access_token = Arc::new(RwLock::new(token.access_token));
+        let cache_ttl = Duration::from_secs(env_or(
+            "SPOTIFY_CACHE_TTL_SECS",
+            DEFAULT_CACHE_TTL_SECS,
+        ));
+        let cache_size = env_or("SPOTIFY_CACHE_SIZE", DEFAULT_CACHE_SIZE);
         SpotifyClient {
             http_client,
             access_token,
             client_id,
             client_secret,
             authorization_code,
+            granted_scopes: token.scope,
+            rate_limiter: RateLimiter::default(),
+            metrics: Metrics::default(),
+            retry_policy: RetryPolicy::from_env(),
+            market,
+            in_flight_gets: SingleFlight::default(),
+            dry_run: config::dry_run(),
+            tracks: TtlCache::new(cache_ttl, cache_size),
+            artist_genres: TtlCache::new(cache_ttl, cache_size),
+            tempos: TtlCache::new(cache_ttl, cache_size),
+            user_names: TtlCache::new(cache_ttl, cache_size),
         }
     }
 
     fn authorize_app(
-        client_id: &String,
+        client_id: &str,
         http_client: &Client,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let params = [
-            ("client_id", client_id.to_string()),
-            ("response_type", "code".to_string()),
-            ("scope", "playlist-modify-public".to_string()),
-            ("redirect_uri", "http://127.0.0.1:5000/callback".to_string()),
-        ];
+        let params = SpotifyClient::authorization_params(client_id);
         let response = http_client
             .get("https://accounts.spotify.com/authorize?")
             .query(&params)
@@ -76,17 +435,109 @@ impl SpotifyClient {
         return Ok(());
     }
 
+    /// Authorization for machines without a browser, such as a server reached
+    /// over SSH. The user opens the printed URL on any device, approves
+    /// access, and pastes back the address Spotify redirected them to.
+    fn authorize_headless(
+        client_id: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let authorize_url = Url::parse_with_params(
+            "https://accounts.spotify.com/authorize",
+            SpotifyClient::authorization_params(client_id),
+        )?;
+        println!(
+            "Open this URL in a browser on any device and approve access:\n\n\
+             {authorize_url}\n\n\
+             The browser will then fail to load a {REDIRECT_URI} page. \
+             Paste the full address of that page here:"
+        );
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let input = input.trim();
+
+        // Accept the bare code as well as the whole redirect URL
+        match Url::parse(input) {
+            Ok(redirect_url) => {
+                let code = redirect_url
+                    .query_pairs()
+                    .find(|(key, _)| key == "code")
+                    .map(|(_, code)| code.into_owned())
+                    .ok_or("The pasted address has no authorization code")?;
+                Ok(code)
+            }
+            Err(_) if !input.is_empty() => Ok(input.to_string()),
+            Err(_) => Err("No authorization code was entered".into()),
+        }
+    }
+
+    fn authorization_params(client_id: &str) -> [(&'static str, String); 4] {
+        [
+            ("client_id", client_id.to_string()),
+            ("response_type", "code".to_string()),
+            ("scope", SpotifyClient::requested_scopes()),
+            ("redirect_uri", REDIRECT_URI.to_string()),
+        ]
+    }
+
+    fn requested_scopes() -> String {
+        let optional_scopes = OPTIONAL_SCOPES.iter().map(|(scope, _)| scope);
+        REQUIRED_SCOPES
+            .iter()
+            .chain(optional_scopes)
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    /// Fails fast when the token lacks a scope the bot cannot work without,
+    /// instead of surfacing it later as an opaque 403 on the first add.
+    fn check_scopes(granted: &str) {
+        let granted: HashSet<&str> = granted.split_whitespace().collect();
+        for (scope, feature) in OPTIONAL_SCOPES {
+            if !granted.contains(scope) {
+                warn!(
+                    "Spotify token is missing the {} scope, {} will not work",
+                    scope, feature
+                );
+            }
+        }
+        let missing: Vec<&str> = REQUIRED_SCOPES
+            .iter()
+            .filter(|scope| !granted.contains(*scope))
+            .copied()
+            .collect();
+        assert!(
+            missing.is_empty(),
+            "Spotify token is missing required scopes: {}",
+            missing.join(", ")
+        );
+    }
+
+    /// The optional scopes the token lacks, with the feature that needs each.
+    /// Empty when Spotify did not report the granted scopes.
+    pub fn missing_optional_scopes(&self) -> Vec<(&'static str, &'static str)> {
+        let Some(granted) = &self.granted_scopes else {
+            return Vec::new();
+        };
+        let granted: HashSet<&str> = granted.split_whitespace().collect();
+        OPTIONAL_SCOPES
+            .iter()
+            .filter(|(scope, _)| !granted.contains(scope))
+            .copied()
+            .collect()
+    }
+
     fn get_access_token(
         client_id: &String,
         client_secret: &String,
         http_client: &Client,
         authorization_code: &String,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    ) -> Result<AccessToken, Box<dyn std::error::Error>> {
         let request_body = json!(
             {
                 "code": authorization_code,
                 "grant_type": "authorization_code",
-                "redirect_uri": "http://127.0.0.1:5000/callback",
+                "redirect_uri": REDIRECT_URI,
             }
         );
         let formatted_credentials = format!("{}:{}", client_id, client_secret);
@@ -100,13 +551,16 @@ impl SpotifyClient {
             .send()?;
 
         let response_body: Value = response.json()?;
-        return Ok(response_body["access_token"].to_string());
+        return Ok(AccessToken {
+            access_token: response_body["access_token"].to_string(),
+            scope: response_body["scope"].as_str().map(String::from),
+        });
     }
 
-    fn build_headers(&self) -> HeaderMap {
+    fn build_headers(access_token: &str) -> HeaderMap {
         let authorization: HeaderValue = HeaderValue::from_str(&format!(
             "Bearer {}",
-            &self.access_token.replace("\"", "")
+            access_token.replace("\"", "")
         ))
         .unwrap();
         let mut headers = HeaderMap::new();
@@ -116,54 +570,188 @@ impl SpotifyClient {
         return headers;
     }
 
-    fn make_get_request(
-        &mut self,
-        endpoint: &str,
-    ) -> Result<Value, Box<dyn std::error::Error>> {
-        let headers: HeaderMap = self.build_headers();
-        let response =
-            self.http_client.get(endpoint).headers(headers).send()?;
+    /// Sends an API request with the access token once the rate limiter
+    /// allows it, pausing every request when Spotify says to slow down and
+    /// refreshing the token when Spotify rejects it, so a retry can succeed.
+    /// Latency and failures are recorded per endpoint.
+    fn send(
+        &self,
+        request: RequestBuilder,
+        deadline: Instant,
+    ) -> Result<Response, SpotifyError> {
+        let access_token = self.access_token.read().unwrap().clone();
+        let request = request
+            .headers(SpotifyClient::build_headers(&access_token))
+            .build()?;
+        let endpoint = format!(
+            "{} {}",
+            request.method(),
+            metrics::endpoint_template(request.url())
+        );
+        self.rate_limiter
+            .wait(deadline)
+            .map_err(SpotifyError::RateLimited)?;
+        let started = Instant::now();
+        let result = self.http_client.execute(request);
+        let failed = !result
+            .as_ref()
+            .is_ok_and(|response| response.status().is_success());
+        self.metrics.record(&endpoint, started.elapsed(), failed);
 
+        let response = result?;
         match response.status() {
-            StatusCode::OK => {
-                let response_body: Value = response.json()?;
-                return Ok(response_body);
+            StatusCode::TOO_MANY_REQUESTS => {
+                let wait = rate_limit::retry_after(response.headers())
+                    .unwrap_or(DEFAULT_RATE_LIMIT_WAIT);
+                self.rate_limiter.pause_for(wait);
             }
             StatusCode::UNAUTHORIZED => {
-                println!("Token expired, retrieving new token and trying again");
-                self.access_token = SpotifyClient::get_access_token(
-                    &self.client_id,
-                    &self.client_secret,
-                    &self.http_client,
-                    &self.authorization_code,
-                )
-                .unwrap();
-                let response_body: Value = response.json()?;
-                return Ok(response_body);
-            }
-            _ => {
-                let response_body: Value = response.json()?;
-                return Ok(response_body);
+                self.refresh_access_token(&access_token)?
             }
+            _ => {}
         }
-        // let response_body: Value = response.json()?;
+        Ok(response)
     }
 
+    /// Replaces `expired` with a new access token, unless another request
+    /// already did.
+    fn refresh_access_token(&self, expired: &str) -> Result<(), SpotifyError> {
+        let mut access_token = self.access_token.write().unwrap();
+        if *access_token != expired {
+            return Ok(());
+        }
+        warn!("Spotify access token expired, retrieving a new one");
+        *access_token = SpotifyClient::get_access_token(
+            &self.client_id,
+            &self.client_secret,
+            &self.http_client,
+            &self.authorization_code,
+        )
+        .map_err(|why| {
+            SpotifyError::UnexpectedResponse(format!(
+                "Could not refresh the access token: {why}"
+            ))
+        })?
+        .access_token;
+        Ok(())
+    }
+
+    /// GETs `endpoint`. Identical GETs made while one is in flight, such as
+    /// for a track linked in several messages at once, share its response
+    /// instead of sending their own. Waiting for that response blocks, so it
+    /// goes through `blocking::run` like the request itself.
+    fn make_get_request(
+        &mut self,
+        endpoint: &str,
+    ) -> Result<Value, SpotifyError> {
+        let in_flight_gets = self.in_flight_gets.clone();
+        blocking::run(|| {
+            in_flight_gets.run(endpoint, || self.send_get_request(endpoint))
+        })
+    }
+
+    fn send_get_request(&self, endpoint: &str) -> Result<Value, SpotifyError> {
+        retry::with_retry(&self.retry_policy, true, |deadline| {
+            let response =
+                self.send(self.http_client.get(endpoint), deadline)?;
+            parse_response(response)
+        })
+    }
+
+    // Adding, moving and removing tracks are not idempotent, so the helpers
+    // below are only retried when Spotify certainly did not act on them
+
     fn make_post_request(
         &self,
         endpoint: &str,
         request_body: serde_json::Value,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let headers: HeaderMap = self.build_headers();
-        let response = self
-            .http_client
-            .post(endpoint)
-            .headers(headers)
-            .json(&request_body)
-            .send()?;
+    ) -> Result<Value, SpotifyError> {
+        if let Some(response) = self.skip_in_dry_run("POST", endpoint) {
+            return Ok(response);
+        }
+        retry::with_retry(&self.retry_policy, false, |deadline| {
+            let response = self.send(
+                self.http_client.post(endpoint).json(&request_body),
+                deadline,
+            )?;
+            parse_response(response)
+        })
+    }
 
-        let response_body: Value = response.json()?;
-        Ok(())
+    fn make_put_request(
+        &self,
+        endpoint: &str,
+        request_body: serde_json::Value,
+    ) -> Result<Value, SpotifyError> {
+        if let Some(response) = self.skip_in_dry_run("PUT", endpoint) {
+            return Ok(response);
+        }
+        retry::with_retry(&self.retry_policy, false, |deadline| {
+            let response = self.send(
+                self.http_client.put(endpoint).json(&request_body),
+                deadline,
+            )?;
+            parse_response(response)
+        })
+    }
+
+    fn make_delete_request(
+        &self,
+        endpoint: &str,
+        request_body: serde_json::Value,
+    ) -> Result<Value, SpotifyError> {
+        if let Some(response) = self.skip_in_dry_run("DELETE", endpoint) {
+            return Ok(response);
+        }
+        retry::with_retry(&self.retry_policy, false, |deadline| {
+            let response = self.send(
+                self.http_client.delete(endpoint).json(&request_body),
+                deadline,
+            )?;
+            parse_response(response)
+        })
+    }
+
+    /// Sends a write that Spotify answers with an empty body, so only the
+    /// status is checked. `build` adds the query or body to the request.
+    fn make_empty_write(
+        &self,
+        method: Method,
+        endpoint: &str,
+        idempotent: bool,
+        build: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<(), SpotifyError> {
+        if self.skip_in_dry_run(method.as_str(), endpoint).is_some() {
+            return Ok(());
+        }
+        retry::with_retry(&self.retry_policy, idempotent, |deadline| {
+            let request = self.http_client.request(method.clone(), endpoint);
+            let response = self.send(build(request), deadline)?;
+            if !response.status().is_success() {
+                return Err(SpotifyError::from_response(response));
+            }
+            Ok(())
+        })
+    }
+
+    /// In a dry run, logs the write that would have been made and returns a
+    /// response with placeholder IDs.
+    fn skip_in_dry_run(&self, method: &str, endpoint: &str) -> Option<Value> {
+        if !self.dry_run {
+            return None;
+        }
+        info!("Dry run, skipping {} {}", method, endpoint);
+        Some(json!({ "id": DRY_RUN_ID, "snapshot_id": DRY_RUN_ID }))
+    }
+
+    /// Whether writes are only logged, see `config::dry_run`.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Statistics of the requests made by this client and its clones.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
     }
 
     pub fn get_artist_details(
@@ -175,16 +763,639 @@ impl SpotifyClient {
         Ok(())
     }
 
-    pub fn get_track_uri(&mut self, track_id: &str) -> String {
-        let endpoint = format!("{API_URL}/tracks/{track_id}");
-        let response = self.make_get_request(&endpoint).unwrap();
-        let uri = response["uri"].to_string().replace("\"", "");
-        return uri;
+    pub fn get_track(
+        &mut self,
+        track_id: &str,
+    ) -> Result<TrackInfo, SpotifyError> {
+        if let Some(track) = self.tracks.get(track_id) {
+            return Ok(track);
+        }
+        // With a market, Spotify relinks the track to a version playable
+        // there and says whether it is playable at all
+        let endpoint = match &self.market {
+            Some(market) => {
+                format!("{API_URL}/tracks/{track_id}?market={market}")
+            }
+            None => format!("{API_URL}/tracks/{track_id}"),
+        };
+        let response = self.make_get_request(&endpoint)?;
+        let track = TrackInfo::from_json(&response).ok_or_else(|| {
+            SpotifyError::UnexpectedResponse(format!(
+                "Track {track_id} has no URI"
+            ))
+        })?;
+        self.tracks.insert(track_id.to_string(), track.clone());
+        Ok(track)
+    }
+
+    /// The name a Spotify user shows on their profile, or their ID if they
+    /// haven't set one.
+    pub fn get_user_display_name(
+        &mut self,
+        user_id: &str,
+    ) -> Result<String, SpotifyError> {
+        if let Some(name) = self.user_names.get(user_id) {
+            return Ok(name);
+        }
+        let response =
+            self.make_get_request(&format!("{API_URL}/users/{user_id}"))?;
+        let name = response["display_name"]
+            .as_str()
+            .filter(|name| !name.is_empty())
+            .unwrap_or(user_id)
+            .to_string();
+        self.user_names.insert(user_id.to_string(), name.clone());
+        Ok(name)
+    }
+
+    /// The artist's most recent albums and singles, newest first.
+    pub fn get_artist_releases(
+        &mut self,
+        artist_id: &str,
+    ) -> Result<Vec<Release>, SpotifyError> {
+        let mut endpoint = format!(
+            "{API_URL}/artists/{artist_id}/albums\
+             ?include_groups=album,single&limit={RELEASES_PAGE_SIZE}"
+        );
+        if let Some(market) = &self.market {
+            endpoint.push_str(&format!("&market={market}"));
+        }
+        let response = self.make_get_request(&endpoint)?;
+        let mut releases: Vec<Release> = response["items"]
+            .as_array()
+            .map(|albums| {
+                albums
+                    .iter()
+                    .filter_map(|album| {
+                        Some(Release {
+                            id: album["id"].as_str()?.to_string(),
+                            name: album["name"]
+                                .as_str()
+                                .unwrap_or_default()
+                                .to_string(),
+                            artists: album["artists"]
+                                .as_array()
+                                .into_iter()
+                                .flatten()
+                                .filter_map(|artist| artist["name"].as_str())
+                                .map(String::from)
+                                .collect(),
+                            kind: album["album_type"]
+                                .as_str()
+                                .unwrap_or_default()
+                                .to_string(),
+                            release_date: album["release_date"]
+                                .as_str()
+                                .unwrap_or_default()
+                                .to_string(),
+                            image_url: album["images"][0]["url"]
+                                .as_str()
+                                .map(String::from),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        // Release dates are ISO 8601, possibly truncated to the year
+        releases.sort_by(|a, b| b.release_date.cmp(&a.release_date));
+        Ok(releases)
+    }
+
+    /// The ID of the first track of an album or single.
+    pub fn get_lead_track_id(
+        &mut self,
+        album_id: &str,
+    ) -> Result<String, SpotifyError> {
+        let endpoint = format!("{API_URL}/albums/{album_id}/tracks?limit=1");
+        let response = self.make_get_request(&endpoint)?;
+        response["items"][0]["id"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| {
+                SpotifyError::UnexpectedResponse(format!(
+                    "Album {album_id} has no tracks"
+                ))
+            })
     }
 
-    pub fn add_to_playlist(&self, track_uri: &str) {
-        let endpoint = format!("{API_URL}/playlists/{PLAYLIST_ID}/tracks");
-        let request_body = json!({ "uris": [track_uri] });
-        let response = self.make_post_request(&endpoint, request_body);
+    /// The genres of each of the given artists, by artist ID.
+    pub fn get_artist_genres(
+        &mut self,
+        artist_ids: &[String],
+    ) -> Result<HashMap<String, Vec<String>>, SpotifyError> {
+        let mut genres = HashMap::new();
+        let mut uncached = Vec::new();
+        for artist_id in artist_ids {
+            match self.artist_genres.get(artist_id) {
+                Some(artist_genres) => {
+                    genres.insert(artist_id.clone(), artist_genres);
+                }
+                None => uncached.push(artist_id.clone()),
+            }
+        }
+        for batch in uncached.chunks(ARTISTS_BATCH_SIZE) {
+            let endpoint = format!("{API_URL}/artists?ids={}", batch.join(","));
+            let response = self.make_get_request(&endpoint)?;
+            for artist in response["artists"].as_array().into_iter().flatten() {
+                let Some(id) = artist["id"].as_str() else {
+                    continue;
+                };
+                let artist_genres = artist["genres"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                self.artist_genres
+                    .insert(id.to_string(), artist_genres.clone());
+                genres.insert(id.to_string(), artist_genres);
+            }
+        }
+        Ok(genres)
+    }
+
+    /// The tempo of each of the given tracks in beats per minute, by track
+    /// ID. Tracks Spotify has not analysed are left out.
+    pub fn get_track_tempos(
+        &mut self,
+        track_ids: &[String],
+    ) -> Result<HashMap<String, f64>, SpotifyError> {
+        let mut tempos = HashMap::new();
+        let mut uncached = Vec::new();
+        for track_id in track_ids {
+            match self.tempos.get(track_id) {
+                Some(tempo) => {
+                    tempos.insert(track_id.clone(), tempo);
+                }
+                None => uncached.push(track_id.clone()),
+            }
+        }
+        for batch in uncached.chunks(AUDIO_FEATURES_BATCH_SIZE) {
+            let endpoint =
+                format!("{API_URL}/audio-features?ids={}", batch.join(","));
+            let response = self.make_get_request(&endpoint)?;
+            for features in
+                response["audio_features"].as_array().into_iter().flatten()
+            {
+                let (Some(id), Some(tempo)) =
+                    (features["id"].as_str(), features["tempo"].as_f64())
+                else {
+                    continue;
+                };
+                self.tempos.insert(id.to_string(), tempo);
+                tempos.insert(id.to_string(), tempo);
+            }
+        }
+        Ok(tempos)
+    }
+
+    /// The best match for `query` among the tracks available in the
+    /// configured market.
+    pub fn search_track(
+        &mut self,
+        query: &str,
+    ) -> Result<Option<TrackInfo>, SpotifyError> {
+        let mut params = vec![("q", query), ("type", "track"), ("limit", "1")];
+        if let Some(market) = &self.market {
+            params.push(("market", market));
+        }
+        let endpoint =
+            Url::parse_with_params(&format!("{API_URL}/search"), &params)
+                .map_err(|why| {
+                    SpotifyError::UnexpectedResponse(why.to_string())
+                })?;
+        let response = self.make_get_request(endpoint.as_str())?;
+        Ok(response["tracks"]["items"]
+            .as_array()
+            .and_then(|tracks| tracks.first())
+            .and_then(TrackInfo::from_json))
+    }
+
+    /// The ID of the first playlist by Spotify's own editors matching `query`.
+    pub fn search_editorial_playlist(
+        &mut self,
+        query: &str,
+    ) -> Result<Option<String>, SpotifyError> {
+        let endpoint = Url::parse_with_params(
+            &format!("{API_URL}/search"),
+            &[("q", query), ("type", "playlist"), ("limit", "5")],
+        )
+        .map_err(|why| SpotifyError::UnexpectedResponse(why.to_string()))?;
+        let response = self.make_get_request(endpoint.as_str())?;
+        // Spotify pads playlist results with nulls for hidden playlists
+        Ok(response["playlists"]["items"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|playlist| playlist["owner"]["id"] == "spotify")
+            .and_then(|playlist| playlist["id"].as_str())
+            .map(String::from))
+    }
+
+    /// Follows an artist on the logged in account.
+    pub fn follow_artist(&self, artist_id: &str) -> Result<(), SpotifyError> {
+        let endpoint =
+            format!("{API_URL}/me/following?type=artist&ids={artist_id}");
+        self.make_empty_write(Method::PUT, &endpoint, true, |request| request)
+    }
+
+    /// Downloads a track's preview clip, refusing clips larger than
+    /// `max_bytes`.
+    pub fn download_preview(
+        &self,
+        preview_url: &str,
+        max_bytes: u64,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let response = self
+            .http_client
+            .get(preview_url)
+            .send()?
+            .error_for_status()?;
+        let mut clip = Vec::new();
+        response.take(max_bytes + 1).read_to_end(&mut clip)?;
+        if clip.len() as u64 > max_bytes {
+            return Err(format!("Preview is over {max_bytes} bytes").into());
+        }
+        Ok(clip)
+    }
+
+    /// Appends a track to the playback queue of the account's active device.
+    pub fn add_to_queue(&self, track_uri: &str) -> Result<(), SpotifyError> {
+        let endpoint = format!("{API_URL}/me/player/queue");
+        self.make_empty_write(Method::POST, &endpoint, false, |request| {
+            request.query(&[("uri", track_uri)]).body("")
+        })
+    }
+
+    /// Appends a track to a playlist and returns the playlist's new snapshot
+    /// ID.
+    pub fn add_to_playlist(
+        &self,
+        playlist_id: &str,
+        track_uri: &str,
+    ) -> Result<String, SpotifyError> {
+        self.add_tracks_to_playlist(playlist_id, &[track_uri.to_string()])
     }
+
+    /// Appends tracks to a playlist in order, and returns the snapshot ID of
+    /// the playlist after the last batch.
+    pub fn add_tracks_to_playlist(
+        &self,
+        playlist_id: &str,
+        track_uris: &[String],
+    ) -> Result<String, SpotifyError> {
+        let endpoint = format!("{API_URL}/playlists/{playlist_id}/tracks");
+        let mut snapshot_id = None;
+        for batch in track_uris.chunks(ADD_BATCH_SIZE) {
+            let request_body = json!({ "uris": batch });
+            let response = self.make_post_request(&endpoint, request_body)?;
+            match response["snapshot_id"].as_str() {
+                Some(id) => snapshot_id = Some(id.to_string()),
+                None => {
+                    return Err(SpotifyError::UnexpectedResponse(format!(
+                        "Spotify did not add the tracks: {response}"
+                    )))
+                }
+            }
+        }
+        snapshot_id.ok_or_else(|| {
+            SpotifyError::UnexpectedResponse("No tracks to add".to_string())
+        })
+    }
+
+    pub fn get_playlist_snapshot(
+        &mut self,
+        playlist_id: &str,
+    ) -> Result<PlaylistSnapshot, SpotifyError> {
+        let endpoint = format!(
+            "{API_URL}/playlists/{playlist_id}\
+             ?fields=name,snapshot_id,tracks.total,owner(id),collaborative"
+        );
+        let response = self.make_get_request(&endpoint)?;
+        let snapshot_id = response["snapshot_id"]
+            .as_str()
+            .ok_or_else(|| {
+                SpotifyError::UnexpectedResponse(
+                    "Playlist response did not include a snapshot ID"
+                        .to_string(),
+                )
+            })?
+            .to_string();
+        let name = response["name"].as_str().unwrap_or_default().to_string();
+        let track_count =
+            response["tracks"]["total"].as_u64().unwrap_or_default() as usize;
+        let owner_id = response["owner"]["id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let collaborative =
+            response["collaborative"].as_bool().unwrap_or_default();
+        Ok(PlaylistSnapshot {
+            name,
+            snapshot_id,
+            track_count,
+            owner_id,
+            collaborative,
+        })
+    }
+
+    /// Replaces every track of a playlist with `track_uris`, in order.
+    pub fn replace_playlist_tracks(
+        &self,
+        playlist_id: &str,
+        track_uris: &[String],
+    ) -> Result<(), SpotifyError> {
+        let endpoint = format!("{API_URL}/playlists/{playlist_id}/tracks");
+        // Replacing takes at most one batch, the rest is appended
+        let (first, rest) =
+            track_uris.split_at(track_uris.len().min(ADD_BATCH_SIZE));
+        self.make_put_request(&endpoint, json!({ "uris": first }))?;
+        if !rest.is_empty() {
+            self.add_tracks_to_playlist(playlist_id, rest)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a public playlist on the logged in account and returns its ID.
+    pub fn create_playlist(
+        &mut self,
+        name: &str,
+        description: &str,
+    ) -> Result<String, SpotifyError> {
+        let user_id = self.get_current_user_id()?;
+        let endpoint = format!("{API_URL}/users/{user_id}/playlists");
+        let request_body = json!({
+            "name": name,
+            "description": description,
+            "public": true,
+        });
+        let response = self.make_post_request(&endpoint, request_body)?;
+        response["id"].as_str().map(String::from).ok_or_else(|| {
+            SpotifyError::UnexpectedResponse(format!(
+                "Spotify did not create the playlist: {response}"
+            ))
+        })
+    }
+
+    pub fn rename_playlist(
+        &self,
+        playlist_id: &str,
+        name: &str,
+    ) -> Result<(), SpotifyError> {
+        let endpoint = format!("{API_URL}/playlists/{playlist_id}");
+        let request_body = json!({ "name": name });
+        self.make_empty_write(Method::PUT, &endpoint, true, |request| {
+            request.json(&request_body)
+        })
+    }
+
+    /// The Spotify user ID of the account the bot is logged in as.
+    pub fn get_current_user_id(&mut self) -> Result<String, SpotifyError> {
+        let response = self.make_get_request(&format!("{API_URL}/me"))?;
+        response["id"].as_str().map(String::from).ok_or_else(|| {
+            SpotifyError::UnexpectedResponse(
+                "User response did not include an ID".to_string(),
+            )
+        })
+    }
+
+    /// The catalogue tracks of a playlist, leaving out local files and
+    /// tracks Spotify no longer serves.
+    pub fn get_playlist_tracks(
+        &mut self,
+        playlist_id: &str,
+    ) -> Result<Vec<TrackInfo>, Box<dyn std::error::Error>> {
+        let items = self.get_playlist_items(playlist_id)?;
+        Ok(items
+            .into_iter()
+            .filter(|item| !item.is_local)
+            .filter_map(|item| item.track)
+            .collect())
+    }
+
+    /// Fetches every item of a playlist in order, so indices match playlist
+    /// positions. Once the first page reveals the playlist size, the remaining
+    /// pages are fetched concurrently. Should Spotify serve a short page, or
+    /// the playlist change size meanwhile, the pages are fetched again one
+    /// at a time by following their `next` links.
+    pub fn get_playlist_items(
+        &mut self,
+        playlist_id: &str,
+    ) -> Result<Vec<PlaylistItem>, Box<dyn std::error::Error>> {
+        let first_page = self.make_get_request(&playlist_items_endpoint(
+            playlist_id,
+            0,
+            self.market.as_deref(),
+        ))?;
+        let total = first_page["total"].as_u64().unwrap_or_default() as usize;
+        let mut items = parse_playlist_page(&first_page);
+
+        let offsets: Vec<usize> = (PLAYLIST_PAGE_SIZE..total)
+            .step_by(PLAYLIST_PAGE_SIZE)
+            .collect();
+        if offsets.is_empty() {
+            return self.complete_playlist_items(playlist_id, items, total);
+        }
+        let workers = offsets.len().min(MAX_CONCURRENT_PAGE_REQUESTS);
+        let pages_per_worker = offsets.len().div_ceil(workers);
+        let market = self.market.as_deref();
+
+        // Each worker fetches a contiguous run of pages with its own handle on
+        // the client, so the pages come back already in order
+        let results: Vec<Result<Vec<PlaylistItem>, String>> =
+            thread::scope(|scope| {
+                let handles: Vec<_> = offsets
+                    .chunks(pages_per_worker)
+                    .map(|worker_offsets| {
+                        let mut client = self.clone();
+                        scope.spawn(move || {
+                            let mut worker_items = Vec::new();
+                            for offset in worker_offsets {
+                                let page = client
+                                    .make_get_request(&playlist_items_endpoint(
+                                        playlist_id,
+                                        *offset,
+                                        market,
+                                    ))
+                                    .map_err(|why| why.to_string())?;
+                                worker_items.extend(parse_playlist_page(&page));
+                            }
+                            Ok(worker_items)
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle.join().unwrap_or_else(|_| {
+                            Err("Playlist page worker panicked".to_string())
+                        })
+                    })
+                    .collect()
+            });
+        for result in results {
+            items.extend(result?);
+        }
+        self.complete_playlist_items(playlist_id, items, total)
+    }
+
+    /// Returns `items` if there are as many as the playlist's `total`, or
+    /// else fetches them again by following each page's `next` link.
+    fn complete_playlist_items(
+        &self,
+        playlist_id: &str,
+        items: Vec<PlaylistItem>,
+        total: usize,
+    ) -> Result<Vec<PlaylistItem>, Box<dyn std::error::Error>> {
+        if items.len() == total {
+            return Ok(items);
+        }
+        warn!(
+            "Got {} of the {} items of playlist {}, refetching in order",
+            items.len(),
+            total,
+            playlist_id
+        );
+        self.playlist_items_iter(playlist_id).collect()
+    }
+
+    /// Whether each of `uris` is in the playlist. Pages through only the
+    /// track URIs, so it is lighter than loading the playlist's items, but
+    /// still reads the whole playlist: prefer `PlaylistCache` where one is
+    /// kept.
+    pub fn playlist_contains(
+        &mut self,
+        playlist_id: &str,
+        uris: &[&str],
+    ) -> Result<Vec<bool>, Box<dyn std::error::Error>> {
+        let mut playlist_uris = HashSet::new();
+        let mut next_endpoint = Some(format!(
+            "{API_URL}/playlists/{playlist_id}/tracks\
+             ?limit={PLAYLIST_PAGE_SIZE}&fields=next,items(track(uri))"
+        ));
+        while let Some(endpoint) = next_endpoint {
+            let page = self.make_get_request(&endpoint)?;
+            if let Some(items) = page["items"].as_array() {
+                playlist_uris.extend(
+                    items
+                        .iter()
+                        .filter_map(|item| item["track"]["uri"].as_str())
+                        .map(String::from),
+                );
+            }
+            next_endpoint = page["next"].as_str().map(String::from);
+        }
+        Ok(uris
+            .iter()
+            .map(|uri| playlist_uris.contains(*uri))
+            .collect())
+    }
+
+    /// Streams the items of a playlist in order, fetching pages as they are
+    /// needed instead of loading the whole playlist up front.
+    pub fn playlist_items_iter(&self, playlist_id: &str) -> PlaylistItems {
+        PlaylistItems {
+            client: self.clone(),
+            next_endpoint: Some(playlist_items_endpoint(
+                playlist_id,
+                0,
+                self.market.as_deref(),
+            )),
+            page: Vec::new().into_iter(),
+        }
+    }
+
+    /// Removes only the given occurrences of each track, leaving other copies
+    /// of the same URI in place. Positions are validated by Spotify against
+    /// `snapshot_id`; the playlist's new snapshot ID is returned.
+    pub fn remove_playlist_positions(
+        &self,
+        playlist_id: &str,
+        snapshot_id: &str,
+        tracks: &[(String, usize)],
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let endpoint = format!("{API_URL}/playlists/{playlist_id}/tracks");
+        let tracks: Vec<Value> = tracks
+            .iter()
+            .map(|(uri, position)| json!({ "uri": uri, "positions": [position] }))
+            .collect();
+        let request_body =
+            json!({ "tracks": tracks, "snapshot_id": snapshot_id });
+        let response = self.make_delete_request(&endpoint, request_body)?;
+        let snapshot_id = response["snapshot_id"]
+            .as_str()
+            .ok_or("Spotify did not accept the removal")?
+            .to_string();
+        Ok(snapshot_id)
+    }
+
+    /// Moves `range_length` items starting at `range_start` so they sit just
+    /// before the item currently at `insert_before`. Returns the playlist's new
+    /// snapshot ID.
+    pub fn reorder_playlist_tracks(
+        &self,
+        playlist_id: &str,
+        snapshot_id: &str,
+        range_start: usize,
+        range_length: usize,
+        insert_before: usize,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let endpoint = format!("{API_URL}/playlists/{playlist_id}/tracks");
+        let request_body = json!({
+            "range_start": range_start,
+            "range_length": range_length,
+            "insert_before": insert_before,
+            "snapshot_id": snapshot_id,
+        });
+        let response = self.make_put_request(&endpoint, request_body)?;
+        let snapshot_id = response["snapshot_id"]
+            .as_str()
+            .ok_or("Spotify did not accept the reorder")?
+            .to_string();
+        Ok(snapshot_id)
+    }
+}
+
+fn parse_response(response: Response) -> Result<Value, SpotifyError> {
+    if !response.status().is_success() {
+        return Err(SpotifyError::from_response(response));
+    }
+    Ok(response.json()?)
+}
+
+fn playlist_items_endpoint(
+    playlist_id: &str,
+    offset: usize,
+    market: Option<&str>,
+) -> String {
+    let mut endpoint = format!(
+        "{API_URL}/playlists/{playlist_id}/tracks\
+         ?limit={PLAYLIST_PAGE_SIZE}&offset={offset}\
+         &fields=total,next,items(added_at,added_by(id),is_local,track(uri,name,\
+         artists(name,id),album(images),external_ids,popularity,duration_ms,\
+         preview_url,is_playable,linked_from(uri)))"
+    );
+    // Spotify carries the market over into the `next` URLs it returns
+    if let Some(market) = market {
+        endpoint.push_str(&format!("&market={market}"));
+    }
+    endpoint
+}
+
+fn parse_playlist_page(page: &Value) -> Vec<PlaylistItem> {
+    page["items"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| PlaylistItem {
+                    track: TrackInfo::from_json(&item["track"]),
+                    added_at: item["added_at"].as_str().map(String::from),
+                    added_by: item["added_by"]["id"].as_str().map(String::from),
+                    is_local: item["is_local"].as_bool().unwrap_or(false),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
 }
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::error;
+use sd_notify::NotifyState;
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::gateway::ConnectionStage;
+use serenity::prelude::Mutex;
+
+// All of these are no-ops unless the bot runs as a `Type=notify` systemd
+// service, which sets NOTIFY_SOCKET.
+
+pub fn notify_ready() {
+    notify(NotifyState::Ready);
+}
+
+pub fn notify_stopping() {
+    notify(NotifyState::Stopping);
+}
+
+/// Pings the systemd watchdog at half its timeout for as long as at least one
+/// shard is connected to Discord, so a hung or disconnected bot is restarted.
+/// Returns straight away when the service has no `WatchdogSec=`.
+pub async fn run_watchdog(shard_manager: Arc<Mutex<ShardManager>>) {
+    let mut timeout_usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut timeout_usec) {
+        return;
+    }
+    let mut interval =
+        tokio::time::interval(Duration::from_micros(timeout_usec) / 2);
+    loop {
+        interval.tick().await;
+        let connected = {
+            let manager = shard_manager.lock().await;
+            let runners = manager.runners.lock().await;
+            runners
+                .values()
+                .any(|runner| runner.stage == ConnectionStage::Connected)
+        };
+        if connected {
+            notify(NotifyState::Watchdog);
+        }
+    }
+}
+
+fn notify(state: NotifyState) {
+    if let Err(why) = sd_notify::notify(false, &[state]) {
+        error!("Could not notify systemd: {:?}", why);
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+
+use crate::chat_bridge::{ChatMessage, ChatSource};
+use crate::config::env_or;
+use crate::secrets;
+
+const API_URL: &str = "https://api.telegram.org";
+// Telegram holds a poll open until a message arrives or this many seconds
+// pass
+const LONG_POLL_SECS: u64 = 25;
+
+/// Telegram groups the bot is a member of, read by long polling the Bot API.
+/// Bots only see ordinary group messages once privacy mode is turned off
+/// with BotFather.
+pub struct TelegramChat {
+    http_client: Client,
+    token: String,
+    /// Only this chat is read, if set.
+    chat_id: Option<i64>,
+    /// The ID after the last update seen, so Telegram doesn't resend it.
+    offset: i64,
+}
+
+impl TelegramChat {
+    /// `None` unless a `TELEGRAM_BOT_TOKEN` is set. `TELEGRAM_CHAT_ID` keeps
+    /// the bot to one group.
+    pub fn from_env(http_client: Client) -> Option<TelegramChat> {
+        let token = secrets::read("TELEGRAM_BOT_TOKEN")?;
+        let chat_id = match env_or("TELEGRAM_CHAT_ID", 0i64) {
+            0 => None,
+            chat_id => Some(chat_id),
+        };
+        Some(TelegramChat {
+            http_client,
+            token,
+            chat_id,
+            offset: 0,
+        })
+    }
+
+    fn call(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        // The token is part of the URL, which must not end up in the logs
+        let mut response: Value = self
+            .http_client
+            .post(format!("{API_URL}/bot{}/{method}", self.token))
+            .json(&params)
+            .timeout(timeout)
+            .send()
+            .and_then(|response| response.json())
+            .map_err(|why| why.without_url())?;
+        if response["ok"] != true {
+            let description =
+                response["description"].as_str().unwrap_or("no description");
+            return Err(
+                format!("Telegram {method} failed: {description}").into()
+            );
+        }
+        Ok(response["result"].take())
+    }
+}
+
+impl ChatSource for TelegramChat {
+    fn name(&self) -> &'static str {
+        "Telegram"
+    }
+
+    fn receive(
+        &mut self,
+    ) -> Result<Vec<ChatMessage>, Box<dyn std::error::Error>> {
+        let updates = self.call(
+            "getUpdates",
+            json!({
+                "offset": self.offset,
+                "timeout": LONG_POLL_SECS,
+                "allowed_updates": ["message"],
+            }),
+            Duration::from_secs(LONG_POLL_SECS + 10),
+        )?;
+        let mut messages = Vec::new();
+        for update in updates.as_array().into_iter().flatten() {
+            if let Some(update_id) = update["update_id"].as_i64() {
+                self.offset = self.offset.max(update_id + 1);
+            }
+            let message = &update["message"];
+            let (Some(text), Some(chat_id), Some(message_id)) = (
+                message["text"].as_str(),
+                message["chat"]["id"].as_i64(),
+                message["message_id"].as_i64(),
+            ) else {
+                continue;
+            };
+            if self.chat_id.is_some_and(|wanted| wanted != chat_id) {
+                continue;
+            }
+            let from = &message["from"];
+            let author = from["username"]
+                .as_str()
+                .map(|username| format!("@{username}"))
+                .or_else(|| from["first_name"].as_str().map(String::from))
+                .unwrap_or_else(|| "someone".to_string());
+            messages.push(ChatMessage {
+                author,
+                text: text.to_string(),
+                chat_id: chat_id.to_string(),
+                message_id: message_id.to_string(),
+            });
+        }
+        Ok(messages)
+    }
+
+    fn reply(
+        &mut self,
+        message: &ChatMessage,
+        content: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.call(
+            "sendMessage",
+            json!({
+                "chat_id": message.chat_id,
+                "text": content,
+                "reply_parameters": {
+                    "message_id": message.message_id.parse::<i64>()?,
+                },
+            }),
+            Duration::from_secs(LONG_POLL_SECS),
+        )?;
+        Ok(())
+    }
+}
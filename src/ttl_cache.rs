@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A bounded cache whose entries expire `ttl` after they were inserted. Once
+/// full, the least recently used entry makes way for new ones. A capacity of
+/// 0 turns caching off. Clones share the same entries.
+#[derive(Clone)]
+pub struct TtlCache<V> {
+    entries: Arc<Mutex<HashMap<String, Entry<V>>>>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    last_used: Instant,
+}
+
+impl<V: Clone> TtlCache<V> {
+    pub fn new(ttl: Duration, capacity: usize) -> TtlCache<V> {
+        TtlCache {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+            capacity,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(key)?;
+        if entry.inserted.elapsed() > self.ttl {
+            entries.remove(key);
+            return None;
+        }
+        entry.last_used = Instant::now();
+        Some(entry.value.clone())
+    }
+
+    pub fn insert(&self, key: String, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.inserted.elapsed() <= self.ttl);
+            if entries.len() >= self.capacity {
+                let least_recent = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(least_recent) = least_recent {
+                    entries.remove(&least_recent);
+                }
+            }
+        }
+        let now = Instant::now();
+        entries.insert(
+            key,
+            Entry {
+                value,
+                inserted: now,
+                last_used: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const LONG_TTL: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let cache = TtlCache::new(Duration::from_millis(20), 10);
+        cache.insert("a".to_string(), 1);
+        assert_eq!(cache.get("a"), Some(1));
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get("a"), None);
+    }
+
+    #[test]
+    fn full_caches_evict_the_least_recently_used() {
+        let cache = TtlCache::new(LONG_TTL, 2);
+        cache.insert("a".to_string(), 1);
+        thread::sleep(Duration::from_millis(1));
+        cache.insert("b".to_string(), 2);
+        thread::sleep(Duration::from_millis(1));
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c".to_string(), 3);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(3));
+    }
+
+    #[test]
+    fn expired_entries_make_way_before_live_ones() {
+        let cache = TtlCache::new(Duration::from_millis(20), 2);
+        cache.insert("old".to_string(), 1);
+        thread::sleep(Duration::from_millis(30));
+        cache.insert("a".to_string(), 2);
+        // Clearing out "old" leaves room, so "a" stays
+        cache.insert("b".to_string(), 3);
+        assert_eq!(cache.get("a"), Some(2));
+        assert_eq!(cache.get("b"), Some(3));
+    }
+
+    #[test]
+    fn replacing_a_key_in_a_full_cache_evicts_nothing() {
+        let cache = TtlCache::new(LONG_TTL, 2);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        cache.insert("a".to_string(), 3);
+        assert_eq!(cache.get("a"), Some(3));
+        assert_eq!(cache.get("b"), Some(2));
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let cache = TtlCache::new(LONG_TTL, 0);
+        cache.insert("a".to_string(), 1);
+        assert_eq!(cache.get("a"), None);
+    }
+
+    #[test]
+    fn clones_share_entries() {
+        let cache = TtlCache::new(LONG_TTL, 2);
+        cache.clone().insert("a".to_string(), 1);
+        assert_eq!(cache.get("a"), Some(1));
+    }
+}
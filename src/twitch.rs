@@ -0,0 +1,103 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use log::info;
+
+use crate::chat_bridge::{ChatMessage, ChatSource};
+use crate::config::env_or;
+
+const IRC_ADDRESS: &str = "irc.chat.twitch.tv:6667";
+// Twitch allows reading chat without an account under any justinfan name
+const ANONYMOUS_NICK: &str = "justinfan31337";
+// Twitch pings every five minutes, a silent connection is a dead one
+const READ_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// A Twitch channel's chat, read anonymously over IRC, so the bot can't
+/// reply.
+pub struct TwitchChat {
+    channel: String,
+    connection: Option<(BufReader<TcpStream>, TcpStream)>,
+}
+
+impl TwitchChat {
+    /// `None` unless `TWITCH_CHANNEL` is set.
+    pub fn from_env() -> Option<TwitchChat> {
+        let channel = env_or("TWITCH_CHANNEL", String::new());
+        if channel.is_empty() {
+            return None;
+        }
+        Some(TwitchChat {
+            channel: channel.trim_start_matches('#').to_lowercase(),
+            connection: None,
+        })
+    }
+
+    fn connect(
+        &self,
+    ) -> Result<(BufReader<TcpStream>, TcpStream), Box<dyn std::error::Error>>
+    {
+        let mut stream = TcpStream::connect(IRC_ADDRESS)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        write!(
+            stream,
+            "NICK {ANONYMOUS_NICK}\r\nJOIN #{}\r\n",
+            self.channel
+        )?;
+        info!("Watching Twitch chat of {}", self.channel);
+        Ok((BufReader::new(stream.try_clone()?), stream))
+    }
+
+    fn read_line(
+        &mut self,
+    ) -> Result<Option<ChatMessage>, Box<dyn std::error::Error>> {
+        if self.connection.is_none() {
+            self.connection = Some(self.connect()?);
+        }
+        let Some((reader, stream)) = &mut self.connection else {
+            return Ok(None);
+        };
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err("Twitch closed the connection".into());
+        }
+        let line = line.trim_end();
+        if let Some(server) = line.strip_prefix("PING ") {
+            write!(stream, "PONG {server}\r\n")?;
+            return Ok(None);
+        }
+        Ok(parse_chat_message(line).map(|(author, text)| ChatMessage {
+            author: author.to_string(),
+            text: text.to_string(),
+            chat_id: self.channel.clone(),
+            message_id: String::new(),
+        }))
+    }
+}
+
+impl ChatSource for TwitchChat {
+    fn name(&self) -> &'static str {
+        "Twitch"
+    }
+
+    fn receive(
+        &mut self,
+    ) -> Result<Vec<ChatMessage>, Box<dyn std::error::Error>> {
+        match self.read_line() {
+            Ok(message) => Ok(message.into_iter().collect()),
+            Err(why) => {
+                self.connection = None;
+                Err(why)
+            }
+        }
+    }
+}
+
+/// The chatter and text of a chat line such as
+/// `:name!name@name.tmi.twitch.tv PRIVMSG #channel :text`.
+fn parse_chat_message(line: &str) -> Option<(&str, &str)> {
+    let (prefix, rest) = line.strip_prefix(':')?.split_once(' ')?;
+    let (_, text) = rest.strip_prefix("PRIVMSG ")?.split_once(" :")?;
+    let chatter = prefix.split('!').next()?;
+    Some((chatter, text))
+}